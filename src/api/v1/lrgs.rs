@@ -1,4 +1,4 @@
-use std::fmt::Debug;
+use std::{collections::BTreeMap, fmt::Debug};
 
 use chrono::{DateTime, Utc};
 use garde::Validate;
@@ -6,6 +6,8 @@ use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use super::rotation::RotationPolicy;
+
 #[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema, Validate)]
 #[kube(
     group = "lrgs.opendcs.org",
@@ -24,10 +26,25 @@ pub struct LrgsClusterSpec {
     pub storage_size: String,
     #[garde(range(min = 0))]
     pub archive_length_days: Option<i32>,
+    /// Regenerate the managed lrgsadmin, replication and routing-user passwords on a schedule.
+    /// A rotation can also be requested at any time by setting the
+    /// `lrgs.opendcs.org/rotate-credentials` annotation to a new value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[garde(skip)]
+    pub credential_rotation: Option<RotationPolicy>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
 pub struct LrgsClusterStatus {
     pub checksum: String,
+    /// Hash of each file of the configuration Secret.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_hashes: BTreeMap<String, String>,
     pub last_updated: Option<DateTime<Utc>>,
+    /// When the managed user passwords were last generated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials_rotated: Option<DateTime<Utc>>,
+    /// Value of the rotate-credentials annotation that was last acted on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_rotation_request: Option<String>,
 }
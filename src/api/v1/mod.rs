@@ -1,4 +1,6 @@
 pub mod dds_recv;
 pub mod drgs;
 pub mod lrgs;
+pub mod netlist;
+pub mod rotation;
 pub mod tsdb;
@@ -0,0 +1,63 @@
+use std::fmt::Debug;
+
+use chrono::{DateTime, Utc};
+use garde::Validate;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Network list of the GOES platforms defined in an OpenDcsDatabase, exported on a schedule and
+/// made available to an LrgsCluster as `<name>.nl` in its netlist directory. Filters combine,
+/// a platform has to match every filter that is set. Only supported for OpenDCS-Postgres.
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema, Validate)]
+#[kube(
+    group = "lrgs.opendcs.org",
+    version = "v1",
+    kind = "Netlist",
+    status = "NetlistStatus",
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct NetlistSpec {
+    /// LrgsCluster, in the same namespace, the netlist is provided to.
+    #[garde(skip)]
+    pub lrgs_cluster: String,
+    /// OpenDcsDatabase, in the same namespace, the platforms are read from.
+    #[garde(skip)]
+    pub database: String,
+    /// Site names, of any name type.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sites: Vec<String>,
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub agencies: Vec<String>,
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platform_designators: Vec<String>,
+    /// Minutes between exports. Defaults to 60.
+    #[garde(range(min = 1))]
+    #[serde(default = "interval_minutes_default")]
+    pub interval_minutes: u32,
+}
+
+fn interval_minutes_default() -> u32 {
+    60
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct NetlistStatus {
+    /// When the netlist was last exported successfully.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_sync: Option<DateTime<Utc>>,
+    /// DCP addresses in the netlist.
+    #[serde(default)]
+    pub address_count: u32,
+    /// Export Job last read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_name: Option<String>,
+    /// Why the last export failed or has not run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub last_updated: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,11 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Schedule for regenerating operator managed passwords.
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationPolicy {
+    /// Days between rotations.
+    #[schemars(range(min = 1))]
+    pub interval_days: u32,
+}
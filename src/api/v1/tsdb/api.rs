@@ -0,0 +1,140 @@
+use std::{collections::BTreeMap, fmt::Debug};
+
+use garde::Validate;
+use k8s_openapi::api::core::v1::{LocalObjectReference, ResourceRequirements};
+use kube::{CustomResource, KubeSchema};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::app::AppStatus;
+
+/// Image used when an OpenDcsApi does not set one.
+pub const API_IMAGE: &str = "ghcr.io/opendcs/web-api:7.0.15-RC03";
+
+/// Port the REST API listens on in the pod.
+pub const API_PORT: i32 = 7000;
+
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, KubeSchema, Validate)]
+#[kube(
+    group = "tsdb.opendcs.org",
+    version = "v1",
+    kind = "OpenDcsApi",
+    status = "AppStatus",
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+#[x_kube(validation = Rule::new("!has(self.ingress) || !has(self.httpRoute)").message("set either ingress or httpRoute"))]
+pub struct OpenDcsApiSpec {
+    /// OpenDcsDatabase, in the same namespace, served by the API.
+    #[garde(skip)]
+    pub database: String,
+    /// Defaults to ghcr.io/opendcs/web-api matching the operator release.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Replicas while the database is Ready.
+    #[garde(range(min = 0))]
+    #[serde(default = "replicas_default")]
+    pub replicas: i32,
+    /// Options passed to the JVM through JAVA_OPTS.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jvm_options: Vec<String>,
+    #[garde(skip)]
+    #[serde(default)]
+    pub authentication: ApiAuthentication,
+    /// Expose the API through an Ingress.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingress: Option<ApiIngress>,
+    /// Expose the API through a Gateway API HTTPRoute.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_route: Option<ApiHttpRoute>,
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceRequirements>,
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub image_pull_secrets: Vec<LocalObjectReference>,
+}
+
+fn replicas_default() -> i32 {
+    1
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub enum AuthenticationType {
+    /// Database users log in with their username and password.
+    #[default]
+    Basic,
+    /// Bearer tokens from an OpenID Connect provider.
+    OpenIdConnect,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAuthentication {
+    /// Accepted ways to authenticate. Defaults to Basic.
+    #[serde(default = "authentication_types_default")]
+    pub types: Vec<AuthenticationType>,
+    /// Required with OpenIdConnect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_id_connect: Option<OpenIdConnect>,
+}
+
+impl Default for ApiAuthentication {
+    fn default() -> Self {
+        Self {
+            types: authentication_types_default(),
+            open_id_connect: None,
+        }
+    }
+}
+
+fn authentication_types_default() -> Vec<AuthenticationType> {
+    vec![AuthenticationType::Basic]
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenIdConnect {
+    pub issuer_url: String,
+    /// Defaults to the jwks_uri published by the issuer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwks_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audience: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiIngress {
+    pub host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingress_class_name: Option<String>,
+    /// Secret with the TLS certificate for the host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_secret_name: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiHttpRoute {
+    /// Gateways the route attaches to.
+    pub parent_refs: Vec<GatewayRef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hostnames: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GatewayRef {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section_name: Option<String>,
+}
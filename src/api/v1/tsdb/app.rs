@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Status shared by the OpenDCS applications deployed against an OpenDcsDatabase.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct AppStatus {
+    /// Whether the referenced database is Ready. Pods are only started while it is.
+    pub database_ready: bool,
+    /// Replicas requested from the Deployment.
+    pub replicas: i32,
+    pub ready_replicas: i32,
+    /// At least one replica is ready.
+    pub running: bool,
+    pub last_updated: Option<DateTime<Utc>>,
+    /// Why pods are not started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
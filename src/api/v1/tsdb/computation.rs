@@ -0,0 +1,52 @@
+use std::fmt::Debug;
+
+use garde::Validate;
+use k8s_openapi::api::core::v1::{LocalObjectReference, ResourceRequirements};
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::app::AppStatus;
+
+/// Image used when a ComputationProcess does not set one.
+pub const COMPPROC_IMAGE: &str = "ghcr.io/opendcs/compproc:7.0.15-RC03";
+
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema, Validate)]
+#[kube(
+    group = "tsdb.opendcs.org",
+    version = "v1",
+    kind = "ComputationProcess",
+    status = "AppStatus",
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ComputationProcessSpec {
+    /// OpenDcsDatabase, in the same namespace, the computations run against.
+    #[garde(skip)]
+    pub database: String,
+    /// Loading application the process runs as (compproc -a).
+    #[garde(skip)]
+    pub app_name: String,
+    /// Defaults to ghcr.io/opendcs/compproc matching the operator release.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Options passed to the JVM through JAVA_OPTS.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jvm_options: Vec<String>,
+    /// Replicas while the database is Ready.
+    #[garde(range(min = 0))]
+    #[serde(default = "replicas_default")]
+    pub replicas: i32,
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceRequirements>,
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub image_pull_secrets: Vec<LocalObjectReference>,
+}
+
+fn replicas_default() -> i32 {
+    1
+}
@@ -2,11 +2,16 @@ use std::{collections::BTreeMap, fmt::Debug};
 
 use chrono::{DateTime, Utc};
 use garde::Validate;
+use k8s_openapi::api::core::v1::{
+    Affinity, LocalObjectReference, ResourceRequirements, Toleration,
+};
 use kube::{CustomResource, KubeSchema, runtime::wait::Condition};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, KubeSchema, Validate)]
+use crate::api::v1::rotation::RotationPolicy;
+
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, Default, KubeSchema, Validate)]
 #[kube(
     group = "tsdb.opendcs.org",
     version = "v1",
@@ -19,22 +24,431 @@ pub struct OpenDcsDatabaseSpec {
     /// Migration image to use. Migration image tags will track the schema version they are as well if the opendcs release version
     #[garde(skip)]
     pub schema_version: String,
-    /// Secret for admin user of the database. Must contain the following keys: jdbc-uri, username, password
+    /// Secret for admin user of the database. Must contain the following keys: jdbc-uri, username, password.
+    /// Oracle based databases additionally require schema-owner.
     #[garde(skip)]
     pub database_secret: String,
+    /// Database implementation. Cannot be changed after initial setup
+    #[garde(skip)]
+    #[serde(default)]
+    #[x_kube(validation = Rule::new("self == oldSelf").message("is immutable"))]
+    pub database_type: DatabaseType,
     #[garde(skip)]
     /// Flyway placeholders for the given database. Cannot be changed after initial setup
     #[x_kube(validation = Rule::new("self == oldSelf").message("is immutable"))]
     pub placeholders: BTreeMap<String, String>,
+    /// How dependent applications are stopped before a migration.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quiesce: Option<QuiesceSpec>,
+    /// Backup taken before migrating an already installed schema. Migrations are blocked if it fails.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<BackupSpec>,
+    /// Additional application users, each given a generated `<name>-app-user-<user>` Secret,
+    /// with underscores in the username replaced by dashes.
+    /// Users removed from this list are dropped from the database. Only supported for OpenDCS-Postgres.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub app_users: Vec<AppUser>,
+    /// Regenerate the passwords of `<name>-app-user` and the application users on a schedule.
+    /// A rotation can also be requested by setting the `tsdb.opendcs.org/rotate-credentials`
+    /// annotation to a new value. Only supported for OpenDCS-Postgres.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_rotation: Option<RotationPolicy>,
+    /// With Manual, a new schemaVersion first records the pending migrations in `status.plan`
+    /// and only migrates once the `tsdb.opendcs.org/approve` annotation is set to that schemaVersion.
+    #[garde(skip)]
+    #[serde(default)]
+    pub approval: Approval,
+    /// Pod and Job settings for the Jobs run against the database.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub migration_job: Option<MigrationJobSpec>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub enum Approval {
+    #[default]
+    Automatic,
+    Manual,
+}
+
+/// Image used for jobs that talk to Postgres directly.
+pub const POSTGRES_CLIENT_IMAGE: &str = "postgres:17";
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSpec {
+    /// PersistentVolumeClaim the backups are written to.
+    pub persistent_volume_claim: String,
+    /// Image providing pg_dump. Defaults to postgres:17
+    #[serde(default = "backup_image_default")]
+    pub image: String,
+    /// Number of backups to keep. Defaults to 5.
+    #[serde(default = "backup_retention_default")]
+    pub retention: u32,
+}
+
+fn backup_image_default() -> String {
+    POSTGRES_CLIENT_IMAGE.to_string()
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct AppUser {
+    /// Database username.
+    #[schemars(regex(pattern = r"^[a-z][a-z0-9_]*$"))]
+    pub name: String,
+    pub role: AppUserRole,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AppUserRole {
+    ReadOnly,
+    User,
+    Admin,
+}
+
+impl AppUserRole {
+    /// Database roles granted to a user with this OpenDCS role.
+    pub fn database_roles(&self) -> &'static [&'static str] {
+        match self {
+            AppUserRole::ReadOnly => &["pg_read_all_data"],
+            AppUserRole::User => &["otsdb_data_acq", "otsdb_comp_exec"],
+            AppUserRole::Admin => &["otsdb_admin", "otsdb_mgr"],
+        }
+    }
+
+    /// Database roles granted through any OpenDCS role.
+    pub fn all_database_roles() -> Vec<&'static str> {
+        [AppUserRole::ReadOnly, AppUserRole::User, AppUserRole::Admin]
+            .iter()
+            .flat_map(|role| role.database_roles().iter().copied())
+            .collect()
+    }
+}
+
+fn backup_retention_default() -> u32 {
+    5
+}
+
+impl OpenDcsDatabaseSpec {
+    /// Placeholders required by the database type that are not set.
+    pub fn missing_placeholders(&self) -> Vec<&'static str> {
+        self.database_type
+            .required_placeholders()
+            .iter()
+            .filter(|p| !self.placeholders.contains_key(**p))
+            .copied()
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub enum DatabaseType {
+    #[default]
+    #[serde(rename = "OpenDCS-Postgres")]
+    OpenDcsPostgres,
+    #[serde(rename = "OpenDCS-Oracle")]
+    OpenDcsOracle,
+    #[serde(rename = "CWMS-Oracle")]
+    CwmsOracle,
+}
+
+impl DatabaseType {
+    /// Name of the implementation as understood by the OpenDCS migration tooling.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DatabaseType::OpenDcsPostgres => "OpenDCS-Postgres",
+            DatabaseType::OpenDcsOracle => "OpenDCS-Oracle",
+            DatabaseType::CwmsOracle => "CWMS-Oracle",
+        }
+    }
+
+    /// Flyway placeholders the migration cannot run without.
+    pub fn required_placeholders(&self) -> &'static [&'static str] {
+        match self {
+            DatabaseType::OpenDcsPostgres => &["NUM_TS_TABLES", "NUM_TEXT_TABLES"],
+            DatabaseType::OpenDcsOracle => {
+                &["NUM_TS_TABLES", "NUM_TEXT_TABLES", "TABLE_SPACE_SPEC"]
+            }
+            DatabaseType::CwmsOracle => &[
+                "CWMS_SCHEMA",
+                "CCP_SCHEMA",
+                "DEFAULT_OFFICE",
+                "TABLE_SPACE_SPEC",
+            ],
+        }
+    }
+
+    /// Keys the database secret must contain.
+    pub fn secret_keys(&self) -> &'static [&'static str] {
+        match self {
+            DatabaseType::OpenDcsPostgres => &["jdbc-uri", "username", "password"],
+            DatabaseType::OpenDcsOracle | DatabaseType::CwmsOracle => {
+                &["jdbc-uri", "username", "password", "schema-owner"]
+            }
+        }
+    }
+
+    pub fn is_postgres(&self) -> bool {
+        *self == DatabaseType::OpenDcsPostgres
+    }
+
+    /// EditDatabaseType of decodes.properties.
+    pub fn decodes_database_type(&self) -> &'static str {
+        match self {
+            DatabaseType::OpenDcsPostgres | DatabaseType::OpenDcsOracle => "OPENTSDB",
+            DatabaseType::CwmsOracle => "CWMS",
+        }
+    }
+
+    pub fn jdbc_driver(&self) -> &'static str {
+        match self {
+            DatabaseType::OpenDcsPostgres => "org.postgresql.Driver",
+            DatabaseType::OpenDcsOracle | DatabaseType::CwmsOracle => {
+                "oracle.jdbc.driver.OracleDriver"
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuiesceSpec {
+    /// Seconds to wait for dependent pods to stop before giving up. Defaults to 600.
+    #[serde(default = "quiesce_timeout_default")]
+    pub timeout_seconds: i64,
+    /// Start the migration once the timeout has passed even if dependent pods are still running.
+    #[serde(default)]
+    pub force: bool,
+}
+
+impl Default for QuiesceSpec {
+    fn default() -> Self {
+        Self {
+            timeout_seconds: quiesce_timeout_default(),
+            force: false,
+        }
+    }
+}
+
+fn quiesce_timeout_default() -> i64 {
+    600
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+/// Applied to the migration Job as well as the plan, check, backup, restore, users and
+/// DecodesImport Jobs.
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationJobSpec {
+    /// Secrets used to pull the migration and client images.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub image_pull_secrets: Vec<LocalObjectReference>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_account_name: Option<String>,
+    /// Resources of the Job container.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceRequirements>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub node_selector: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tolerations: Vec<Toleration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub affinity: Option<Affinity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff_limit: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_deadline_seconds: Option<i64>,
+    /// Jobs removed sooner than the controller looks at them are treated as never run,
+    /// so keep this to at least several minutes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_seconds_after_finished: Option<i32>,
+    /// Finished Jobs of each kind to keep. Defaults to 3.
+    #[serde(default = "job_history_limit_default")]
+    pub history_limit: u32,
+}
+
+impl Default for MigrationJobSpec {
+    fn default() -> Self {
+        Self {
+            image_pull_secrets: Vec::new(),
+            service_account_name: None,
+            resources: None,
+            node_selector: BTreeMap::new(),
+            tolerations: Vec::new(),
+            affinity: None,
+            backoff_limit: None,
+            active_deadline_seconds: None,
+            ttl_seconds_after_finished: None,
+            history_limit: job_history_limit_default(),
+        }
+    }
+}
+
+fn job_history_limit_default() -> u32 {
+    3
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
 pub struct OpenDcsDatabaseStatus {
-    /// Applied Schema version as derived from the installed schema
+    /// schemaVersion (migration image) last applied by the operator, or the version recorded
+    /// with a restored backup. What the database itself reports is in `installed_schema`.
     pub applied_schema_version: Option<String>,
     /// Current migration activity
     pub state: Option<MigrationState>,
     pub last_updated: Option<DateTime<Utc>>,
+    /// When dependent workloads were asked to stop for the current migration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quiesce_started: Option<DateTime<Utc>>,
+    /// Workloads scaled to zero for the current migration and the replicas to restore once Ready.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quiesced_workloads: Vec<QuiescedWorkload>,
+    /// Details of the last failed migration. Cleared when a new migration starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<MigrationFailure>,
+    /// Value of the `tsdb.opendcs.org/retry-migration` annotation last acted on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_retry: Option<String>,
+    /// Backups taken by the operator, newest last.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backups: Vec<BackupRecord>,
+    /// Restore requested through the `tsdb.opendcs.org/restore` annotation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restore: Option<RestoreStatus>,
+    /// Application users applied by the last successful users Job.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub app_users: Vec<AppUser>,
+    /// When the operator managed passwords were last rotated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials_rotated: Option<DateTime<Utc>>,
+    /// Value of the `tsdb.opendcs.org/rotate-credentials` annotation last acted on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_rotation_request: Option<String>,
+    /// Job applying new passwords. The Secrets are only updated once it succeeds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation_job: Option<String>,
+    /// Migrations a new schemaVersion would apply, recorded when approval is Manual.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<MigrationPlan>,
+    /// Schema found in flyway_schema_history by the last check Job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_schema: Option<InstalledSchema>,
+    /// Set when the schema history changed without the operator migrating. Cleared by the
+    /// next migration or restore.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_drift: Option<SchemaDrift>,
+    /// Migrations run by the operator, newest last. Bounded to MAX_HISTORY entries.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<MigrationRecord>,
+}
+
+/// Number of migrations kept in `status.history`.
+pub const MAX_HISTORY: usize = 20;
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct MigrationRecord {
+    pub from_version: Option<String>,
+    pub to_version: String,
+    pub started: DateTime<Utc>,
+    pub finished: Option<DateTime<Utc>>,
+    /// Unset while the migration is running.
+    pub outcome: Option<MigrationOutcome>,
+    pub job_name: String,
+    /// Backup taken before the migration.
+    pub backup: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub enum MigrationOutcome {
+    Succeeded,
+    Failed,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct InstalledSchema {
+    /// Latest successfully applied Flyway version. Unset when no schema is installed.
+    pub version: Option<String>,
+    /// Digest over the applied migrations and their Flyway checksums.
+    pub checksum: Option<String>,
+    /// Number of successfully applied migrations.
+    pub migrations: u32,
+    /// applied_schema_version at the time of the check.
+    pub schema_version: Option<String>,
+    pub job_name: String,
+    pub checked: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct SchemaDrift {
+    pub previous_checksum: Option<String>,
+    pub checksum: Option<String>,
+    pub detected: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct MigrationPlan {
+    /// schemaVersion the plan was made for.
+    pub schema_version: String,
+    /// Schema version installed when the plan was made.
+    pub from_version: Option<String>,
+    pub job_name: String,
+    /// Pending Flyway migrations, in the order they will be applied.
+    pub pending: Vec<PendingMigration>,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct PendingMigration {
+    /// Flyway version, empty for repeatable migrations.
+    pub version: String,
+    pub description: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct RestoreStatus {
+    /// Name of the backup being restored.
+    pub backup: String,
+    /// Schema version recorded with the backup.
+    pub schema_version: Option<String>,
+    /// Spec generation at the time of the restore. Migrations are held until the spec changes.
+    pub observed_generation: Option<i64>,
+    pub started: DateTime<Utc>,
+    pub completed: Option<DateTime<Utc>>,
+    /// Restore Job started for this restore, once dependents are quiesced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct BackupRecord {
+    /// Name of the backup, also the name of the Job that created it.
+    pub name: String,
+    /// Schema version installed when the backup was taken.
+    pub schema_version: Option<String>,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct MigrationFailure {
+    pub job_name: String,
+    pub reason: String,
+    pub message: String,
+    /// Tail of the migration pod's output.
+    pub logs: Option<String>,
+    /// Spec generation the failed migration ran against.
+    pub observed_generation: Option<i64>,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// A Deployment or StatefulSet labeled `tsdb.opendcs.org/for-database` that was scaled down.
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct QuiescedWorkload {
+    pub kind: String,
+    pub name: String,
+    pub replicas: i32,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
@@ -44,14 +458,26 @@ pub struct OpenDcsDatabaseStatus {
 pub enum MigrationState {
     // Schema not yet installed.
     Fresh,
+    // Reading the schema history of a database the operator has not migrated yet.
+    Checking,
+    // Listing the migrations a new schemaVersion would apply.
+    Planning,
+    // Migration planned, waiting on the approve annotation.
+    AwaitingApproval,
     // Waiting for apps to shutdown.
     PreparingToMigrate,
+    // Backing up the database before migrating.
+    BackingUp,
     // Applying Schema updates.
     Migrating,
     // Apps can start connecting to database again.
     Ready,
     // Schema migration failed and requires user intervention..
     Failed,
+    // Restoring a backup taken by the operator.
+    Restoring,
+    // Restoring a backup failed and requires user intervention.
+    RestoreFailed,
 }
 
 impl Condition<OpenDcsDatabase> for OpenDcsDatabase {
@@ -0,0 +1,86 @@
+use std::fmt::Debug;
+
+use chrono::{DateTime, Utc};
+use garde::Validate;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Image providing dbimport when a DecodesImport does not set one.
+pub const DBIMPORT_IMAGE: &str = "ghcr.io/opendcs/compproc:7.0.15-RC03";
+
+/// Loads DECODES XML (platforms, sites, configs, routing specs, ...) into an OpenDcsDatabase
+/// with dbimport. The XML is imported again whenever the content of the ConfigMaps changes.
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema, Validate)]
+#[kube(
+    group = "tsdb.opendcs.org",
+    version = "v1",
+    kind = "DecodesImport",
+    status = "DecodesImportStatus",
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodesImportSpec {
+    /// OpenDcsDatabase, in the same namespace, the XML is imported into.
+    #[garde(skip)]
+    pub database: String,
+    /// ConfigMaps, in the same namespace, holding the XML files. Every key is imported.
+    #[garde(length(min = 1))]
+    pub config_maps: Vec<String>,
+    /// Keep records already in the database instead of overwriting them (dbimport -o).
+    #[garde(skip)]
+    #[serde(default)]
+    pub keep_existing: bool,
+    /// Only validate the XML, nothing is written (dbimport -v).
+    #[garde(skip)]
+    #[serde(default)]
+    pub validate_only: bool,
+    /// Defaults to an OpenDCS image matching the operator release.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub enum ImportState {
+    /// The database is not Ready or a ConfigMap is missing.
+    Waiting,
+    Importing,
+    Imported,
+    Failed,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct DecodesImportStatus {
+    pub state: Option<ImportState>,
+    /// Hash of the XML currently in the ConfigMaps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// Hash of the XML last imported successfully.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imported_hash: Option<String>,
+    /// Result of the most recent dbimport Job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_import: Option<ImportRecord>,
+    /// Why nothing is imported while Waiting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub last_updated: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub enum ImportOutcome {
+    Succeeded,
+    Failed,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct ImportRecord {
+    pub job_name: String,
+    pub content_hash: String,
+    pub outcome: ImportOutcome,
+    pub finished: DateTime<Utc>,
+    /// Last lines of the dbimport output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
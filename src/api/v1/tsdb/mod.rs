@@ -1 +1,6 @@
+pub mod api;
+pub mod app;
+pub mod computation;
 pub mod database;
+pub mod import;
+pub mod routing;
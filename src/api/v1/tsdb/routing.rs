@@ -0,0 +1,50 @@
+use std::fmt::Debug;
+
+use garde::Validate;
+use k8s_openapi::api::core::v1::{LocalObjectReference, ResourceRequirements};
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::app::AppStatus;
+
+/// Image used when a RoutingScheduler does not set one.
+pub const ROUTING_SCHEDULER_IMAGE: &str = "ghcr.io/opendcs/routingscheduler:7.0.15-RC03";
+
+/// Runs the DECODES routing specs of a loading application, reading from an LrgsCluster and
+/// writing into an OpenDcsDatabase. The LRGS is available to the routing specs through the
+/// LRGS_HOST, LRGS_PORT, LRGS_USERNAME and LRGS_PASSWORD environment variables.
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema, Validate)]
+#[kube(
+    group = "tsdb.opendcs.org",
+    version = "v1",
+    kind = "RoutingScheduler",
+    status = "AppStatus",
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutingSchedulerSpec {
+    /// OpenDcsDatabase, in the same namespace, holding the routing specs and receiving the data.
+    #[garde(skip)]
+    pub database: String,
+    /// LrgsCluster, in the same namespace, data is read from as its managed routing-user.
+    #[garde(skip)]
+    pub lrgs_cluster: String,
+    /// Loading application whose routing specs are run (routsched -a).
+    #[garde(skip)]
+    pub app_name: String,
+    /// Defaults to ghcr.io/opendcs/routingscheduler matching the operator release.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Options passed to the JVM through JAVA_OPTS.
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jvm_options: Vec<String>,
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceRequirements>,
+    #[garde(skip)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub image_pull_secrets: Vec<LocalObjectReference>,
+}
@@ -0,0 +1,384 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    api::v1::tsdb::{
+        api::{API_IMAGE, API_PORT, ApiAuthentication, AuthenticationType, OpenDcsApi},
+        app::AppStatus,
+        database::OpenDcsDatabase,
+    },
+    apps::database::{
+        DatabaseApp, DatabaseBinding, UsesDatabase, app_deployment, apply_app, bind_database,
+        client_config_env, dependents, jvm_env, selector_labels,
+    },
+    settings::scoped_api,
+    telemetry::{
+        state::{Context, State},
+        telemetry,
+    },
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use futures::StreamExt;
+use k8s_openapi::{
+    api::{
+        apps::v1::Deployment,
+        core::v1::{
+            Container, ContainerPort, Probe, SecurityContext, Service, ServicePort, ServiceSpec,
+            TCPSocketAction,
+        },
+        networking::v1::{
+            HTTPIngressPath, HTTPIngressRuleValue, Ingress, IngressBackend, IngressRule,
+            IngressServiceBackend, IngressSpec, IngressTLS, ServiceBackendPort,
+        },
+    },
+    apimachinery::pkg::util::intstr::IntOrString,
+};
+use kube::{
+    Api, Client, Error, Resource, ResourceExt,
+    api::{
+        ApiResource, DeleteParams, DynamicObject, GroupVersionKind, ObjectMeta, Patch, PatchParams,
+    },
+    runtime::{Controller, controller::Action, watcher},
+};
+use serde_json::json;
+use tracing::{Span, error, field, info, instrument, warn};
+
+const APP_NAME: &str = "web-api";
+
+impl UsesDatabase for OpenDcsApi {
+    fn database(&self) -> &str {
+        &self.spec.database
+    }
+}
+
+impl DatabaseApp for OpenDcsApi {
+    fn app_status(&self) -> Option<&AppStatus> {
+        self.status.as_ref()
+    }
+}
+
+/// System properties configuring how clients authenticate.
+fn authentication_options(authentication: &ApiAuthentication) -> Vec<String> {
+    let types: Vec<&str> = authentication
+        .types
+        .iter()
+        .map(|t| match t {
+            AuthenticationType::Basic => "basic",
+            AuthenticationType::OpenIdConnect => "openid",
+        })
+        .collect();
+    let mut options = vec![format!(
+        "-Dopendcs.rest.api.authorization.type={}",
+        types.join(",")
+    )];
+    if let Some(oidc) = &authentication.open_id_connect {
+        options.push(format!(
+            "-Dopendcs.rest.api.authorization.jwt.issuer.url={}",
+            oidc.issuer_url
+        ));
+        if let Some(jwks_url) = &oidc.jwks_url {
+            options.push(format!(
+                "-Dopendcs.rest.api.authorization.jwt.jwkset.url={jwks_url}"
+            ));
+        }
+        if let Some(audience) = &oidc.audience {
+            options.push(format!(
+                "-Dopendcs.rest.api.authorization.jwt.audience={audience}"
+            ));
+        }
+    }
+    options
+}
+
+pub fn create_deployment(api: &OpenDcsApi, binding: &DatabaseBinding) -> Deployment {
+    let database = &api.spec.database;
+    let mut options = authentication_options(&api.spec.authentication);
+    options.extend(api.spec.jvm_options.iter().cloned());
+    let mut env = vec![
+        client_config_env(database, "DATABASE_URL"),
+        client_config_env(database, "DATABASE_DRIVER"),
+        client_config_env(database, "DATABASE_TYPE"),
+    ];
+    env.extend(jvm_env(&options));
+    let container = Container {
+        name: APP_NAME.to_string(),
+        image: Some(api.spec.image.clone().unwrap_or(API_IMAGE.to_string())),
+        ports: Some(vec![ContainerPort {
+            container_port: API_PORT,
+            name: Some("http".to_string()),
+            protocol: Some("TCP".to_string()),
+            ..Default::default()
+        }]),
+        readiness_probe: Some(Probe {
+            tcp_socket: Some(TCPSocketAction {
+                port: IntOrString::String("http".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        env: Some(env),
+        resources: api.spec.resources.clone(),
+        security_context: Some(SecurityContext {
+            allow_privilege_escalation: Some(false),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let mut deployment = app_deployment(
+        api,
+        APP_NAME,
+        binding,
+        api.spec.replicas,
+        container,
+        BTreeMap::new(),
+        &api.spec.image_pull_secrets,
+    );
+    // The API holds no locks, replicas can be replaced one at a time.
+    if let Some(spec) = deployment.spec.as_mut() {
+        spec.strategy = None;
+    }
+    deployment
+}
+
+pub fn create_service(api: &OpenDcsApi) -> Service {
+    Service {
+        metadata: ObjectMeta {
+            name: Some(api.name_any()),
+            namespace: api.namespace(),
+            owner_references: Some(vec![api.controller_owner_ref(&()).unwrap()]),
+            ..Default::default()
+        },
+        spec: Some(ServiceSpec {
+            type_: Some("ClusterIP".to_string()),
+            ports: Some(vec![ServicePort {
+                name: Some("http".to_string()),
+                port: 80,
+                target_port: Some(IntOrString::String("http".to_string())),
+                protocol: Some("TCP".to_string()),
+                ..Default::default()
+            }]),
+            selector: Some(selector_labels(APP_NAME, &api.name_any())),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+pub fn create_ingress(api: &OpenDcsApi) -> Option<Ingress> {
+    let ingress = api.spec.ingress.as_ref()?;
+    Some(Ingress {
+        metadata: ObjectMeta {
+            name: Some(api.name_any()),
+            namespace: api.namespace(),
+            owner_references: Some(vec![api.controller_owner_ref(&()).unwrap()]),
+            annotations: Some(ingress.annotations.clone()).filter(|a| !a.is_empty()),
+            ..Default::default()
+        },
+        spec: Some(IngressSpec {
+            ingress_class_name: ingress.ingress_class_name.clone(),
+            rules: Some(vec![IngressRule {
+                host: Some(ingress.host.clone()),
+                http: Some(HTTPIngressRuleValue {
+                    paths: vec![HTTPIngressPath {
+                        path: Some("/".to_string()),
+                        path_type: "Prefix".to_string(),
+                        backend: IngressBackend {
+                            service: Some(IngressServiceBackend {
+                                name: api.name_any(),
+                                port: Some(ServiceBackendPort {
+                                    name: Some("http".to_string()),
+                                    ..Default::default()
+                                }),
+                            }),
+                            ..Default::default()
+                        },
+                    }],
+                }),
+            }]),
+            tls: ingress.tls_secret_name.as_ref().map(|secret| {
+                vec![IngressTLS {
+                    hosts: Some(vec![ingress.host.clone()]),
+                    secret_name: Some(secret.clone()),
+                }]
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+fn http_route_resource() -> ApiResource {
+    ApiResource::from_gvk(&GroupVersionKind::gvk(
+        "gateway.networking.k8s.io",
+        "v1",
+        "HTTPRoute",
+    ))
+}
+
+/// Gateway API HTTPRoute, built dynamically as the Gateway API types are not part of
+/// k8s-openapi.
+pub fn create_http_route(api: &OpenDcsApi) -> Option<DynamicObject> {
+    let route = api.spec.http_route.as_ref()?;
+    let resource = http_route_resource();
+    let mut object = DynamicObject::new(&api.name_any(), &resource).data(json!({
+        "spec": {
+            "parentRefs": route.parent_refs,
+            "hostnames": route.hostnames,
+            "rules": [{
+                "backendRefs": [{"name": api.name_any(), "port": 80}]
+            }]
+        }
+    }));
+    object.metadata.namespace = api.namespace();
+    object.metadata.owner_references = Some(vec![api.controller_owner_ref(&()).unwrap()]);
+    Some(object)
+}
+
+/// Delete an object that is no longer configured, if it exists.
+async fn delete_if_present<K>(api: &Api<K>, name: &str) -> Result<(), Error>
+where
+    K: Clone + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    match api.delete(name, &DeleteParams::background()).await {
+        Ok(_) => Ok(()),
+        Err(Error::Api(e)) if e.code == 404 => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn run(state: State<OpenDcsApi>, client: Client) {
+    let settings = state.settings.clone();
+    settings
+        .for_each_namespace(|namespace| run_in(state.clone(), client.clone(), namespace))
+        .await;
+}
+
+async fn run_in(state: State<OpenDcsApi>, client: Client, namespace: Option<String>) {
+    let apis: Api<OpenDcsApi> = scoped_api(client.clone(), namespace.as_deref());
+    let deployments: Api<Deployment> = scoped_api(client.clone(), namespace.as_deref());
+    let services: Api<Service> = scoped_api(client.clone(), namespace.as_deref());
+    let databases: Api<OpenDcsDatabase> = scoped_api(client.clone(), namespace.as_deref());
+    println!("Starting controller");
+    let controller = Controller::new(apis, watcher::Config::default())
+        .with_config(state.settings.controller_config());
+    let store = controller.store();
+    controller
+        .owns(deployments, watcher::Config::default())
+        .owns(services, watcher::Config::default())
+        .watches(databases, watcher::Config::default(), move |db| {
+            dependents(&store, &db)
+        })
+        .shutdown_on_signal()
+        .run(reconcile, error_policy, state.to_context(client).await)
+        .filter_map(|x| async move { std::result::Result::ok(x) })
+        .for_each(|_| futures::future::ready(()))
+        .await;
+}
+
+#[instrument(skip(object, ctx), fields(trace_id))]
+async fn reconcile(
+    object: Arc<OpenDcsApi>,
+    ctx: Arc<Context<OpenDcsApi>>,
+) -> Result<Action, Error> {
+    let trace_id = telemetry::get_trace_id();
+    if trace_id != opentelemetry::trace::TraceId::INVALID {
+        Span::current().record("trace_id", field::display(&trace_id));
+    }
+    let _timer = ctx.metrics.reconcile.count_and_measure(&trace_id);
+    ctx.diagnostics.write().await.last_event = Utc::now();
+    let client = &ctx.client;
+    let name = object.name_any();
+    let ns = object.namespace().unwrap_or("default".to_string());
+    info!("Processing \"{}\" in {}", name, ns);
+    let services: Api<Service> = Api::namespaced(client.clone(), &ns);
+    let ingresses: Api<Ingress> = Api::namespaced(client.clone(), &ns);
+    let routes: Api<DynamicObject> =
+        Api::namespaced_with(client.clone(), &ns, &http_route_resource());
+    let pp = PatchParams::apply("apps-controller");
+
+    let mut binding = match bind_database(client, &ns, &object.spec.database).await {
+        Ok(binding) => binding,
+        Err(e) => {
+            error!(
+                "Unable to look up database {} for {}/{}: {:?}",
+                object.spec.database, ns, name, e
+            );
+            ctx.metrics.reconcile.set_failure(&object, &e);
+            return Ok(Action::requeue(ctx.settings.error_backoff));
+        }
+    };
+    let authentication = &object.spec.authentication;
+    if authentication
+        .types
+        .contains(&AuthenticationType::OpenIdConnect)
+        && authentication.open_id_connect.is_none()
+    {
+        binding.ready = false;
+        binding.waiting_for =
+            Some("authentication.openIdConnect is required with OpenIdConnect".to_string());
+    }
+    if let Some(waiting_for) = &binding.waiting_for {
+        info!("{}/{} stays scaled down: {}", ns, name, waiting_for);
+    }
+
+    services
+        .patch(&name, &pp, &Patch::Apply(create_service(&object)))
+        .await?;
+    match create_ingress(&object) {
+        Some(ingress) => {
+            ingresses.patch(&name, &pp, &Patch::Apply(ingress)).await?;
+        }
+        None => delete_if_present(&ingresses, &name).await?,
+    }
+    match create_http_route(&object) {
+        Some(route) => {
+            routes.patch(&name, &pp, &Patch::Apply(route)).await?;
+        }
+        None => delete_if_present(&routes, &name).await?,
+    }
+    let deployment = create_deployment(&object, &binding);
+    apply_app(client, &*object, &binding, deployment).await?;
+    Ok(Action::requeue(ctx.settings.requeue))
+}
+
+fn error_policy(
+    object: Arc<OpenDcsApi>,
+    err: &kube::Error,
+    ctx: Arc<Context<OpenDcsApi>>,
+) -> Action {
+    warn!("reconcile failed: {:?}", err);
+    let e = anyhow!("Api error {:?}", err);
+    ctx.metrics.reconcile.set_failure(&object, &e);
+    Action::requeue(ctx.settings.error_backoff)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::v1::tsdb::api::OpenIdConnect;
+
+    #[test]
+    fn authentication_is_passed_as_system_properties() {
+        assert_eq!(
+            authentication_options(&ApiAuthentication::default()),
+            vec!["-Dopendcs.rest.api.authorization.type=basic".to_string()]
+        );
+        let options = authentication_options(&ApiAuthentication {
+            types: vec![AuthenticationType::Basic, AuthenticationType::OpenIdConnect],
+            open_id_connect: Some(OpenIdConnect {
+                issuer_url: "https://login.example.com".to_string(),
+                jwks_url: None,
+                audience: Some("opendcs".to_string()),
+            }),
+        });
+        assert_eq!(
+            options,
+            vec![
+                "-Dopendcs.rest.api.authorization.type=basic,openid".to_string(),
+                "-Dopendcs.rest.api.authorization.jwt.issuer.url=https://login.example.com"
+                    .to_string(),
+                "-Dopendcs.rest.api.authorization.jwt.audience=opendcs".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    api::v1::tsdb::{
+        app::AppStatus,
+        computation::{COMPPROC_IMAGE, ComputationProcess},
+        database::OpenDcsDatabase,
+    },
+    apps::database::{
+        CLIENT_CONFIG_DIR, DatabaseApp, DatabaseBinding, UsesDatabase, app_deployment, apply_app,
+        bind_database, dependents, jvm_env,
+    },
+    settings::scoped_api,
+    telemetry::{
+        state::{Context, State},
+        telemetry,
+    },
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use futures::StreamExt;
+use k8s_openapi::api::{
+    apps::v1::Deployment,
+    core::v1::{Container, SecurityContext},
+};
+use kube::{
+    Api, Client, Error, ResourceExt,
+    runtime::{Controller, controller::Action, watcher},
+};
+use tracing::{Span, error, field, info, instrument, warn};
+
+impl UsesDatabase for ComputationProcess {
+    fn database(&self) -> &str {
+        &self.spec.database
+    }
+}
+
+impl DatabaseApp for ComputationProcess {
+    fn app_status(&self) -> Option<&AppStatus> {
+        self.status.as_ref()
+    }
+}
+
+pub fn create_deployment(process: &ComputationProcess, binding: &DatabaseBinding) -> Deployment {
+    let container = Container {
+        name: "compproc".to_string(),
+        image: Some(
+            process
+                .spec
+                .image
+                .clone()
+                .unwrap_or(COMPPROC_IMAGE.to_string()),
+        ),
+        command: Some(vec![
+            "compproc".to_string(),
+            "-a".to_string(),
+            process.spec.app_name.clone(),
+            "-P".to_string(),
+            format!("{CLIENT_CONFIG_DIR}/decodes.properties"),
+            "-l".to_string(),
+            "/dev/stdout".to_string(),
+        ]),
+        env: Some(jvm_env(&process.spec.jvm_options).into_iter().collect()),
+        resources: process.spec.resources.clone(),
+        security_context: Some(SecurityContext {
+            allow_privilege_escalation: Some(false),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    app_deployment(
+        process,
+        "compproc",
+        binding,
+        process.spec.replicas,
+        container,
+        BTreeMap::new(),
+        &process.spec.image_pull_secrets,
+    )
+}
+
+pub async fn run(state: State<ComputationProcess>, client: Client) {
+    let settings = state.settings.clone();
+    settings
+        .for_each_namespace(|namespace| run_in(state.clone(), client.clone(), namespace))
+        .await;
+}
+
+async fn run_in(state: State<ComputationProcess>, client: Client, namespace: Option<String>) {
+    let processes: Api<ComputationProcess> = scoped_api(client.clone(), namespace.as_deref());
+    let deployments: Api<Deployment> = scoped_api(client.clone(), namespace.as_deref());
+    let databases: Api<OpenDcsDatabase> = scoped_api(client.clone(), namespace.as_deref());
+    println!("Starting controller");
+    let controller = Controller::new(processes, watcher::Config::default())
+        .with_config(state.settings.controller_config());
+    let store = controller.store();
+    controller
+        .owns(deployments, watcher::Config::default())
+        .watches(databases, watcher::Config::default(), move |db| {
+            dependents(&store, &db)
+        })
+        .shutdown_on_signal()
+        .run(reconcile, error_policy, state.to_context(client).await)
+        .filter_map(|x| async move { std::result::Result::ok(x) })
+        .for_each(|_| futures::future::ready(()))
+        .await;
+}
+
+#[instrument(skip(object, ctx), fields(trace_id))]
+async fn reconcile(
+    object: Arc<ComputationProcess>,
+    ctx: Arc<Context<ComputationProcess>>,
+) -> Result<Action, Error> {
+    let trace_id = telemetry::get_trace_id();
+    if trace_id != opentelemetry::trace::TraceId::INVALID {
+        Span::current().record("trace_id", field::display(&trace_id));
+    }
+    let _timer = ctx.metrics.reconcile.count_and_measure(&trace_id);
+    ctx.diagnostics.write().await.last_event = Utc::now();
+    let client = &ctx.client;
+    let name = object.name_any();
+    let ns = object.namespace().unwrap_or("default".to_string());
+    info!("Processing \"{}\" in {}", name, ns);
+    let binding = match bind_database(client, &ns, &object.spec.database).await {
+        Ok(binding) => binding,
+        Err(e) => {
+            error!(
+                "Unable to look up database {} for {}/{}: {:?}",
+                object.spec.database, ns, name, e
+            );
+            ctx.metrics.reconcile.set_failure(&object, &e);
+            return Ok(Action::requeue(ctx.settings.error_backoff));
+        }
+    };
+    if let Some(waiting_for) = &binding.waiting_for {
+        info!("{}/{} stays scaled down: {}", ns, name, waiting_for);
+    }
+    let deployment = create_deployment(&object, &binding);
+    apply_app(client, &*object, &binding, deployment).await?;
+    Ok(Action::requeue(ctx.settings.requeue))
+}
+
+fn error_policy(
+    object: Arc<ComputationProcess>,
+    err: &kube::Error,
+    ctx: Arc<Context<ComputationProcess>>,
+) -> Action {
+    warn!("reconcile failed: {:?}", err);
+    let e = anyhow!("Api error {:?}", err);
+    ctx.metrics.reconcile.set_failure(&object, &e);
+    Action::requeue(ctx.settings.error_backoff)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::v1::tsdb::computation::ComputationProcessSpec;
+
+    fn process() -> ComputationProcess {
+        let mut process = ComputationProcess::new(
+            "compproc",
+            ComputationProcessSpec {
+                database: "tsdb".to_string(),
+                app_name: "compproc".to_string(),
+                image: None,
+                jvm_options: vec![],
+                replicas: 2,
+                resources: None,
+                image_pull_secrets: vec![],
+            },
+        );
+        process.metadata.namespace = Some("test".to_string());
+        process.metadata.uid = Some("1".to_string());
+        process
+    }
+
+    #[test]
+    fn pods_wait_for_a_ready_database() {
+        let deployment = create_deployment(
+            &process(),
+            &DatabaseBinding {
+                ready: false,
+                config_hash: None,
+                waiting_for: Some("database".to_string()),
+            },
+        );
+        let spec = deployment.spec.unwrap();
+        assert_eq!(spec.replicas, Some(0));
+        assert_eq!(
+            deployment
+                .metadata
+                .labels
+                .unwrap()
+                .get("tsdb.opendcs.org/for-database"),
+            Some(&"tsdb".to_string())
+        );
+
+        let deployment = create_deployment(
+            &process(),
+            &DatabaseBinding {
+                ready: true,
+                config_hash: Some("abc".to_string()),
+                waiting_for: None,
+            },
+        );
+        assert_eq!(deployment.spec.unwrap().replicas, Some(2));
+    }
+}
@@ -0,0 +1,331 @@
+use std::{collections::BTreeMap, fmt::Debug};
+
+use crate::{
+    api::{
+        constants::TSDB_GROUP,
+        v1::tsdb::{
+            app::AppStatus,
+            database::{MigrationState, OpenDcsDatabase},
+        },
+    },
+    schema::{
+        client_config::{client_config_name, client_credentials_name},
+        quiesce::for_database_label,
+    },
+};
+use anyhow::Result;
+use chrono::Utc;
+use k8s_openapi::{
+    NamespaceResourceScope,
+    api::{
+        apps::v1::{Deployment, DeploymentSpec, DeploymentStrategy},
+        core::v1::{
+            ConfigMap, ConfigMapKeySelector, ConfigMapVolumeSource, Container, EnvVar,
+            EnvVarSource, LocalObjectReference, PodSpec, PodTemplateSpec, SecretKeySelector,
+            Volume, VolumeMount,
+        },
+    },
+    apimachinery::pkg::apis::meta::v1::LabelSelector,
+};
+use kube::{
+    Api, Client, Resource, ResourceExt,
+    api::{ObjectMeta, Patch, PatchParams},
+    runtime::reflector::{ObjectRef, Store},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::json;
+
+/// Where the client ConfigMap of the database is mounted in application pods.
+pub const CLIENT_CONFIG_DIR: &str = "/opendcs-config";
+
+/// A resource referencing an OpenDcsDatabase in its own namespace.
+pub trait UsesDatabase:
+    Resource<DynamicType = (), Scope = NamespaceResourceScope>
+    + Clone
+    + Debug
+    + Serialize
+    + DeserializeOwned
+{
+    fn database(&self) -> &str;
+}
+
+/// An application deployed against an OpenDcsDatabase.
+pub trait DatabaseApp: UsesDatabase {
+    fn app_status(&self) -> Option<&AppStatus>;
+}
+
+/// What an application needs to know about its database.
+pub struct DatabaseBinding {
+    /// The database reports Ready, and whatever else the application needs is in place.
+    /// Pods are not started otherwise.
+    pub ready: bool,
+    /// Hash of the client configuration and credentials, rolls the pods when either changes.
+    pub config_hash: Option<String>,
+    /// Reported in the status while not ready.
+    pub waiting_for: Option<String>,
+}
+
+pub async fn bind_database(
+    client: &Client,
+    namespace: &str,
+    database: &str,
+) -> Result<DatabaseBinding> {
+    let databases: Api<OpenDcsDatabase> = Api::namespaced(client.clone(), namespace);
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let ready = databases.get_opt(database).await?.is_some_and(|db| {
+        db.status
+            .is_some_and(|s| s.state == Some(MigrationState::Ready))
+    });
+    let config_hash = config_maps
+        .get_opt(&client_config_name(database))
+        .await?
+        .and_then(|cm| {
+            cm.annotations()
+                .get(&format!("{}/config-hash", TSDB_GROUP.as_str()))
+                .cloned()
+        });
+    let waiting_for = if !ready {
+        Some(format!(
+            "Waiting for OpenDcsDatabase {database} to be Ready"
+        ))
+    } else if config_hash.is_none() {
+        Some(format!(
+            "Waiting for the client configuration of {database}"
+        ))
+    } else {
+        None
+    };
+    Ok(DatabaseBinding {
+        ready: waiting_for.is_none(),
+        config_hash,
+        waiting_for,
+    })
+}
+
+/// Resources in the namespace of `database` that reference it.
+pub fn dependents<K: UsesDatabase>(
+    store: &Store<K>,
+    database: &OpenDcsDatabase,
+) -> Vec<ObjectRef<K>> {
+    store
+        .state()
+        .into_iter()
+        .filter(|app| {
+            app.database() == database.name_any() && app.namespace() == database.namespace()
+        })
+        .map(|app| ObjectRef::from_obj(&*app))
+        .collect()
+}
+
+/// Labels of an application Deployment. Only the name and instance are used as the selector,
+/// the `for-database` label lets the schema controller quiesce it during migrations.
+pub fn app_labels(app: &str, instance: &str, database: &str) -> BTreeMap<String, String> {
+    let mut labels = selector_labels(app, instance);
+    let (key, value) = for_database_label(database);
+    labels.insert(key, value);
+    labels
+}
+
+pub fn selector_labels(app: &str, instance: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("app.kubernetes.io/name".to_string(), app.to_string()),
+        (
+            "app.kubernetes.io/instance".to_string(),
+            instance.to_string(),
+        ),
+    ])
+}
+
+/// DATABASE_USERNAME and DATABASE_PASSWORD from the client credentials, as referenced by the
+/// generated decodes.properties.
+pub fn database_env(database: &str) -> Vec<EnvVar> {
+    ["DATABASE_USERNAME", "DATABASE_PASSWORD"]
+        .iter()
+        .map(|key| EnvVar {
+            name: key.to_string(),
+            value_from: Some(EnvVarSource {
+                secret_key_ref: Some(SecretKeySelector {
+                    name: client_credentials_name(database),
+                    key: key.to_string(),
+                    optional: Some(false),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Variable read from one of the connection settings in the client ConfigMap.
+pub fn client_config_env(database: &str, key: &str) -> EnvVar {
+    EnvVar {
+        name: key.to_string(),
+        value_from: Some(EnvVarSource {
+            config_map_key_ref: Some(ConfigMapKeySelector {
+                name: client_config_name(database),
+                key: key.to_string(),
+                optional: Some(false),
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Volume with decodes.properties, mounted at CLIENT_CONFIG_DIR.
+pub fn client_config_volume(database: &str) -> (Volume, VolumeMount) {
+    (
+        Volume {
+            name: "client-config".to_string(),
+            config_map: Some(ConfigMapVolumeSource {
+                name: client_config_name(database),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        VolumeMount {
+            name: "client-config".to_string(),
+            mount_path: CLIENT_CONFIG_DIR.to_string(),
+            read_only: Some(true),
+            ..Default::default()
+        },
+    )
+}
+
+/// JAVA_OPTS from a list of JVM options.
+pub fn jvm_env(options: &[String]) -> Option<EnvVar> {
+    (!options.is_empty()).then(|| EnvVar {
+        name: "JAVA_OPTS".to_string(),
+        value: Some(options.join(" ")),
+        ..Default::default()
+    })
+}
+
+/// Deployment running `container` against the database of `app`. The database credentials
+/// and client configuration are added to the container, and the pods roll when the client
+/// configuration changes. Pods are replaced with the Recreate strategy so two processes of
+/// the same application never run at once.
+pub fn app_deployment<K: DatabaseApp>(
+    app: &K,
+    app_name: &str,
+    binding: &DatabaseBinding,
+    replicas: i32,
+    mut container: Container,
+    mut annotations: BTreeMap<String, String>,
+    image_pull_secrets: &[LocalObjectReference],
+) -> Deployment {
+    let owner_ref = app.controller_owner_ref(&()).unwrap();
+    let name = app.name_any();
+    let labels = app_labels(app_name, &name, app.database());
+    if let Some(hash) = &binding.config_hash {
+        annotations.insert(format!("{}/config-hash", TSDB_GROUP.as_str()), hash.clone());
+    }
+    let (volume, volume_mount) = client_config_volume(app.database());
+    let mut env = database_env(app.database());
+    env.extend(container.env.take().unwrap_or_default());
+    container.env = Some(env);
+    container
+        .volume_mounts
+        .get_or_insert_default()
+        .push(volume_mount);
+
+    Deployment {
+        metadata: ObjectMeta {
+            name: Some(name.clone()),
+            namespace: app.namespace(),
+            owner_references: Some(vec![owner_ref]),
+            labels: Some(labels.clone()),
+            ..Default::default()
+        },
+        spec: Some(DeploymentSpec {
+            replicas: Some(gated_replicas(binding, replicas)),
+            selector: LabelSelector {
+                match_labels: Some(selector_labels(app_name, &name)),
+                ..Default::default()
+            },
+            strategy: Some(DeploymentStrategy {
+                type_: Some("Recreate".to_string()),
+                ..Default::default()
+            }),
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: Some(labels),
+                    annotations: Some(annotations),
+                    ..Default::default()
+                }),
+                spec: Some(PodSpec {
+                    containers: vec![container],
+                    image_pull_secrets: Some(image_pull_secrets.to_vec()).filter(|s| !s.is_empty()),
+                    volumes: Some(vec![volume]),
+                    ..Default::default()
+                }),
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Apply the Deployment of an application and record how it is doing in its status.
+pub async fn apply_app<K: DatabaseApp>(
+    client: &Client,
+    app: &K,
+    binding: &DatabaseBinding,
+    deployment: Deployment,
+) -> Result<(), kube::Error> {
+    let name = app.name_any();
+    let namespace = app.namespace().unwrap_or("default".to_string());
+    let apps: Api<K> = Api::namespaced(client.clone(), &namespace);
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), &namespace);
+    // Forced, replicas are also changed by the schema controller while it quiesces.
+    let pp = PatchParams::apply("apps-controller").force();
+    let deployment = deployments
+        .patch(&name, &pp, &Patch::Apply(deployment))
+        .await?;
+
+    let new_status = app_status(binding, &deployment);
+    let unchanged = app.app_status().is_some_and(|s| {
+        AppStatus {
+            last_updated: None,
+            ..s.clone()
+        } == AppStatus {
+            last_updated: None,
+            ..new_status.clone()
+        }
+    });
+    if !unchanged {
+        let new_status = Patch::Apply(json!({
+            "apiVersion": K::api_version(&()),
+            "kind": K::kind(&()),
+            "status": new_status
+        }));
+        apps.patch_status(&name, &pp, &new_status).await?;
+    }
+    Ok(())
+}
+
+/// Replicas to request: none until the database is Ready.
+pub fn gated_replicas(binding: &DatabaseBinding, replicas: i32) -> i32 {
+    if binding.ready { replicas } else { 0 }
+}
+
+pub fn app_status(binding: &DatabaseBinding, deployment: &Deployment) -> AppStatus {
+    let replicas = deployment
+        .spec
+        .as_ref()
+        .and_then(|s| s.replicas)
+        .unwrap_or(0);
+    let ready_replicas = deployment
+        .status
+        .as_ref()
+        .and_then(|s| s.ready_replicas)
+        .unwrap_or(0);
+    AppStatus {
+        database_ready: binding.ready,
+        replicas,
+        ready_replicas,
+        running: ready_replicas > 0,
+        last_updated: Some(Utc::now()),
+        message: binding.waiting_for.clone(),
+    }
+}
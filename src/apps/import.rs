@@ -0,0 +1,397 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use crate::{
+    api::{
+        constants::TSDB_GROUP,
+        v1::tsdb::{
+            database::OpenDcsDatabase,
+            import::{
+                DBIMPORT_IMAGE, DecodesImport, DecodesImportStatus, ImportOutcome, ImportRecord,
+                ImportState,
+            },
+        },
+    },
+    apps::database::{
+        CLIENT_CONFIG_DIR, UsesDatabase, bind_database, client_config_volume, database_env,
+        dependents,
+    },
+    schema::{
+        job::{failed_condition, job_logs, latest_job, run_job},
+        quiesce::for_database_label,
+    },
+    settings::scoped_api,
+    telemetry::{
+        state::{Context, State},
+        telemetry,
+    },
+};
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use futures::StreamExt;
+use k8s_openapi::api::{
+    batch::v1::{Job, JobSpec},
+    core::v1::{
+        ConfigMap, ConfigMapVolumeSource, Container, PodSpec, PodTemplateSpec, SecurityContext,
+        Volume, VolumeMount,
+    },
+};
+use kube::{
+    Api, Client, Error, Resource, ResourceExt,
+    api::{ObjectMeta, Patch, PatchParams},
+    runtime::{Controller, controller::Action, reflector::ObjectRef, watcher},
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::{Span, error, field, info, instrument, warn};
+
+impl UsesDatabase for DecodesImport {
+    fn database(&self) -> &str {
+        &self.spec.database
+    }
+}
+
+fn content_hash_annotation() -> String {
+    format!("{}/content-hash", TSDB_GROUP.as_str())
+}
+
+/// Hash over the name, keys and values of the ConfigMaps, in the order they are listed.
+pub fn content_hash(config_maps: &[ConfigMap]) -> String {
+    let mut hasher = Sha256::new();
+    for config_map in config_maps {
+        hasher.update(config_map.name_any().as_bytes());
+        for (key, value) in config_map.data.iter().flatten() {
+            hasher.update(key.as_bytes());
+            hasher.update(value.as_bytes());
+        }
+    }
+    base16ct::lower::encode_string(&hasher.finalize())
+}
+
+/// Job running dbimport over every file of the ConfigMaps, mounted at /import/<ConfigMap>.
+pub fn create_import_job(
+    import: &DecodesImport,
+    job_name: &str,
+    config_maps: &[ConfigMap],
+    hash: &str,
+) -> Job {
+    let owner_ref = import.controller_owner_ref(&()).unwrap();
+    let labels = BTreeMap::from([("import-job".to_string(), import.name_any())]);
+    // Migrations wait for a running import like they do for application pods.
+    let mut pod_labels = labels.clone();
+    let (key, value) = for_database_label(&import.spec.database);
+    pod_labels.insert(key, value);
+    let (config_volume, config_mount) = client_config_volume(&import.spec.database);
+    let mut volumes = vec![config_volume];
+    let mut volume_mounts = vec![config_mount];
+    let mut command = vec![
+        "dbimport".to_string(),
+        "-P".to_string(),
+        format!("{CLIENT_CONFIG_DIR}/decodes.properties"),
+        "-l".to_string(),
+        "/dev/stdout".to_string(),
+    ];
+    if import.spec.keep_existing {
+        command.push("-o".to_string());
+    }
+    if import.spec.validate_only {
+        command.push("-v".to_string());
+    }
+    for (i, config_map) in config_maps.iter().enumerate() {
+        let name = config_map.name_any();
+        volumes.push(Volume {
+            name: format!("import-{i}"),
+            config_map: Some(ConfigMapVolumeSource {
+                name: name.clone(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        volume_mounts.push(VolumeMount {
+            name: format!("import-{i}"),
+            mount_path: format!("/import/{name}"),
+            read_only: Some(true),
+            ..Default::default()
+        });
+        command.extend(
+            config_map
+                .data
+                .iter()
+                .flatten()
+                .map(|(key, _)| format!("/import/{name}/{key}")),
+        );
+    }
+
+    Job {
+        metadata: ObjectMeta {
+            name: Some(job_name.to_string()),
+            namespace: import.namespace(),
+            owner_references: Some(vec![owner_ref]),
+            labels: Some(labels.clone()),
+            annotations: Some(BTreeMap::from([(
+                content_hash_annotation(),
+                hash.to_string(),
+            )])),
+            ..Default::default()
+        },
+        spec: Some(JobSpec {
+            backoff_limit: Some(1),
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: Some(pod_labels),
+                    ..Default::default()
+                }),
+                spec: Some(PodSpec {
+                    containers: vec![Container {
+                        name: "dbimport".to_string(),
+                        image: Some(
+                            import
+                                .spec
+                                .image
+                                .clone()
+                                .unwrap_or(DBIMPORT_IMAGE.to_string()),
+                        ),
+                        command: Some(command),
+                        security_context: Some(SecurityContext {
+                            allow_privilege_escalation: Some(false),
+                            ..Default::default()
+                        }),
+                        env: Some(database_env(&import.spec.database)),
+                        volume_mounts: Some(volume_mounts),
+                        ..Default::default()
+                    }],
+                    volumes: Some(volumes),
+                    restart_policy: Some("Never".to_string()),
+                    ..Default::default()
+                }),
+            },
+            ..Default::default()
+        }),
+        status: None,
+    }
+}
+
+/// Work out the import state, recording finished Jobs and starting a new one when the XML
+/// differs from what the latest Job imported. A failed import is only retried once the
+/// content changes.
+async fn reconcile_import(
+    client: &Client,
+    import: &DecodesImport,
+    status: &mut DecodesImportStatus,
+) -> Result<()> {
+    let namespace = import.namespace().unwrap_or("default".to_string());
+    let config_map_api: Api<ConfigMap> = Api::namespaced(client.clone(), &namespace);
+    let mut config_maps = Vec::new();
+    for name in &import.spec.config_maps {
+        match config_map_api.get_opt(name).await? {
+            Some(config_map) => config_maps.push(config_map),
+            None => {
+                status.state = Some(ImportState::Waiting);
+                status.message = Some(format!("Waiting for ConfigMap {name}"));
+                return Ok(());
+            }
+        }
+    }
+    let hash = content_hash(&config_maps);
+    status.content_hash = Some(hash.clone());
+
+    let latest = latest_job(
+        client.clone(),
+        &namespace,
+        &format!("import-job={}", import.name_any()),
+    )
+    .await?;
+    let latest_hash = latest
+        .as_ref()
+        .and_then(|j| j.annotations().get(&content_hash_annotation()).cloned());
+    if let Some(job) = &latest {
+        let job_name = job.name_any();
+        let succeeded = job.status.as_ref().and_then(|s| s.succeeded).unwrap_or(0) > 0;
+        let failed = failed_condition(job).is_some();
+        if !succeeded && !failed {
+            status.state = Some(ImportState::Importing);
+            status.message = None;
+            return Ok(());
+        }
+        if status.last_import.as_ref().map(|r| &r.job_name) != Some(&job_name) {
+            let job_hash = latest_hash.clone().unwrap_or_default();
+            let (outcome, state) = if succeeded {
+                status.imported_hash = Some(job_hash.clone());
+                (ImportOutcome::Succeeded, ImportState::Imported)
+            } else {
+                (ImportOutcome::Failed, ImportState::Failed)
+            };
+            status.last_import = Some(ImportRecord {
+                job_name: job_name.clone(),
+                content_hash: job_hash,
+                outcome,
+                finished: Utc::now(),
+                output: job_logs(client.clone(), &namespace, &job_name, 20).await,
+            });
+            status.state = Some(state);
+        }
+    }
+    if latest_hash.as_ref() == Some(&hash) {
+        status.message = None;
+        return Ok(());
+    }
+
+    let binding = bind_database(client, &namespace, &import.spec.database).await?;
+    let databases: Api<OpenDcsDatabase> = Api::namespaced(client.clone(), &namespace);
+    let database = match databases.get_opt(&import.spec.database).await? {
+        Some(database) if binding.ready => database,
+        _ => {
+            status.state = Some(ImportState::Waiting);
+            status.message = binding.waiting_for;
+            return Ok(());
+        }
+    };
+    let job_name = format!(
+        "{}-import-{}",
+        import.name_any(),
+        Utc::now().format("%Y%m%d%H%M%S")
+    );
+    info!("Creating import job {}/{}", &namespace, &job_name);
+    run_job(
+        client,
+        &database,
+        create_import_job(import, &job_name, &config_maps, &hash),
+    )
+    .await?;
+    status.state = Some(ImportState::Importing);
+    status.message = None;
+    Ok(())
+}
+
+pub async fn run(state: State<DecodesImport>, client: Client) {
+    let settings = state.settings.clone();
+    settings
+        .for_each_namespace(|namespace| run_in(state.clone(), client.clone(), namespace))
+        .await;
+}
+
+async fn run_in(state: State<DecodesImport>, client: Client, namespace: Option<String>) {
+    let imports: Api<DecodesImport> = scoped_api(client.clone(), namespace.as_deref());
+    let jobs: Api<Job> = scoped_api(client.clone(), namespace.as_deref());
+    let config_maps: Api<ConfigMap> = scoped_api(client.clone(), namespace.as_deref());
+    let databases: Api<OpenDcsDatabase> = scoped_api(client.clone(), namespace.as_deref());
+    println!("Starting controller");
+    let controller = Controller::new(imports, watcher::Config::default())
+        .with_config(state.settings.controller_config());
+    let store = controller.store();
+    let database_store = store.clone();
+    controller
+        .owns(jobs, watcher::Config::default())
+        .watches(databases, watcher::Config::default(), move |db| {
+            dependents(&database_store, &db)
+        })
+        .watches(
+            config_maps,
+            watcher::Config::default(),
+            move |config_map: ConfigMap| {
+                store
+                    .state()
+                    .into_iter()
+                    .filter(|i| {
+                        i.spec.config_maps.contains(&config_map.name_any())
+                            && i.namespace() == config_map.namespace()
+                    })
+                    .map(|i| ObjectRef::from_obj(&*i))
+                    .collect::<Vec<_>>()
+            },
+        )
+        .shutdown_on_signal()
+        .run(reconcile, error_policy, state.to_context(client).await)
+        .filter_map(|x| async move { std::result::Result::ok(x) })
+        .for_each(|_| futures::future::ready(()))
+        .await;
+}
+
+#[instrument(skip(object, ctx), fields(trace_id))]
+async fn reconcile(
+    object: Arc<DecodesImport>,
+    ctx: Arc<Context<DecodesImport>>,
+) -> Result<Action, Error> {
+    let trace_id = telemetry::get_trace_id();
+    if trace_id != opentelemetry::trace::TraceId::INVALID {
+        Span::current().record("trace_id", field::display(&trace_id));
+    }
+    let _timer = ctx.metrics.reconcile.count_and_measure(&trace_id);
+    ctx.diagnostics.write().await.last_event = Utc::now();
+    let client = &ctx.client;
+    let name = object.name_any();
+    let ns = object.namespace().unwrap_or("default".to_string());
+    info!("Processing \"{}\" in {}", name, ns);
+    let imports: Api<DecodesImport> = Api::namespaced(client.clone(), &ns);
+
+    let mut status = object.status.clone().unwrap_or_default();
+    if let Err(e) = reconcile_import(client, &object, &mut status).await {
+        error!("Unable to import into {}/{}: {:?}", ns, name, e);
+        ctx.metrics.reconcile.set_failure(&object, &e);
+        return Ok(Action::requeue(ctx.settings.error_backoff));
+    }
+    let state = status.state.clone();
+    let current = object.status.clone().map(|s| DecodesImportStatus {
+        last_updated: None,
+        ..s
+    });
+    if current
+        != Some(DecodesImportStatus {
+            last_updated: None,
+            ..status.clone()
+        })
+    {
+        status.last_updated = Some(Utc::now());
+        let new_status = Patch::Apply(json!({
+            "apiVersion": "tsdb.opendcs.org/v1",
+            "kind": "DecodesImport",
+            "status": status
+        }));
+        let pp = PatchParams::apply("apps-controller").force();
+        imports.patch_status(&name, &pp, &new_status).await?;
+    }
+    let requeue = match state {
+        Some(ImportState::Importing) => Duration::from_secs(15),
+        _ => ctx.settings.requeue,
+    };
+    Ok(Action::requeue(requeue))
+}
+
+fn error_policy(
+    object: Arc<DecodesImport>,
+    err: &kube::Error,
+    ctx: Arc<Context<DecodesImport>>,
+) -> Action {
+    warn!("reconcile failed: {:?}", err);
+    let e = anyhow!("Api error {:?}", err);
+    ctx.metrics.reconcile.set_failure(&object, &e);
+    Action::requeue(ctx.settings.error_backoff)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config_map(name: &str, xml: &str) -> ConfigMap {
+        ConfigMap {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
+            data: Some(BTreeMap::from([("sites.xml".to_string(), xml.to_string())])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn content_hash_follows_the_xml() {
+        let sites = config_map("sites", "<Database/>");
+        assert_eq!(
+            content_hash(std::slice::from_ref(&sites)),
+            content_hash(&[config_map("sites", "<Database/>")])
+        );
+        assert_ne!(
+            content_hash(&[sites]),
+            content_hash(&[config_map("sites", "<Database><Site/></Database>")])
+        );
+    }
+}
@@ -0,0 +1,6 @@
+pub mod api;
+pub mod computation;
+pub mod database;
+pub mod import;
+pub mod netlist;
+pub mod routing;
@@ -0,0 +1,309 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use crate::{
+    api::v1::{
+        netlist::{Netlist, NetlistStatus},
+        tsdb::database::{DatabaseType, OpenDcsDatabase, POSTGRES_CLIENT_IMAGE},
+    },
+    apps::database::{UsesDatabase, bind_database, dependents},
+    schema::job::{
+        ScriptJob, failed_condition, job_logs, latest_job, pod_output, run_job, script_job,
+    },
+    settings::Settings,
+    settings::scoped_api,
+    telemetry::{
+        state::{Context, State},
+        telemetry,
+    },
+};
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use futures::StreamExt;
+use k8s_openapi::api::{
+    batch::v1::Job,
+    core::v1::{ConfigMap, EnvVar},
+};
+use kube::{
+    Api, Client, Error, Resource, ResourceExt,
+    api::{ObjectMeta, Patch, PatchParams},
+    runtime::{Controller, controller::Action, watcher},
+};
+use serde_json::json;
+use tracing::{Span, error, field, info, instrument, warn};
+
+impl UsesDatabase for Netlist {
+    fn database(&self) -> &str {
+        &self.spec.database
+    }
+}
+
+/// ConfigMap holding the exported netlist, collected into the netlists ConfigMap of its
+/// LrgsCluster.
+pub fn netlist_config_map_name(netlist: &str) -> String {
+    format!("{netlist}-netlist")
+}
+
+/// File name of the netlist in the LRGS netlist directory.
+pub fn netlist_file_name(netlist: &str) -> String {
+    format!("{netlist}.nl")
+}
+
+/// Job printing the GOES platforms of the database matching the filters of the netlist.
+pub fn create_export_job(netlist: &Netlist, database: &OpenDcsDatabase, job_name: &str) -> Job {
+    let script = String::from_utf8(Vec::from(include_bytes!("netlist.sh"))).unwrap_or_default();
+    let filter = |name: &str, values: &[String]| EnvVar {
+        name: name.to_string(),
+        value: Some(values.join(",")),
+        ..Default::default()
+    };
+    let mut job = script_job(
+        database,
+        ScriptJob {
+            job_name: job_name.to_string(),
+            label: ("netlist-job", netlist.name_any()),
+            annotations: BTreeMap::new(),
+            image: POSTGRES_CLIENT_IMAGE.to_string(),
+            script,
+            env: vec![
+                filter("NETLIST_SITES", &netlist.spec.sites),
+                filter("NETLIST_AGENCIES", &netlist.spec.agencies),
+                filter("NETLIST_DESIGNATORS", &netlist.spec.platform_designators),
+            ],
+            volumes: vec![],
+        },
+    );
+    // Owned by the netlist rather than the database so finished exports trigger a sync.
+    job.metadata.owner_references = Some(vec![netlist.controller_owner_ref(&()).unwrap()]);
+    job
+}
+
+/// Netlist file from the output of netlist.sh, one `ADDRESS:NAME DESCRIPTION` line per platform.
+/// Returns the file and the number of addresses in it.
+pub fn render_netlist(output: &str) -> (String, u32) {
+    let mut netlist = String::new();
+    let mut count = 0;
+    for line in output.lines().map(str::trim) {
+        let Some(fields) = line.strip_prefix("NETLIST|") else {
+            continue;
+        };
+        let mut fields = fields.splitn(3, '|');
+        let address = fields.next().unwrap_or_default().trim();
+        if address.is_empty() {
+            continue;
+        }
+        let name = fields
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join("_");
+        let description = fields.next().unwrap_or_default().trim();
+        netlist.push_str(format!("{address}:{name} {description}").trim_end());
+        netlist.push('\n');
+        count += 1;
+    }
+    (netlist, count)
+}
+
+/// Sync the netlist from the latest export Job and start the next one once the interval has
+/// passed. Returns how long to wait before looking again.
+async fn reconcile_netlist(
+    client: &Client,
+    netlist: &Netlist,
+    status: &mut NetlistStatus,
+    settings: &Settings,
+) -> Result<Duration> {
+    let namespace = netlist.namespace().unwrap_or("default".to_string());
+    let name = netlist.name_any();
+    let interval = chrono::Duration::minutes(netlist.spec.interval_minutes as i64);
+    let latest = latest_job(client.clone(), &namespace, &format!("netlist-job={name}")).await?;
+    if let Some(job) = &latest {
+        let job_name = job.name_any();
+        let succeeded = job.status.as_ref().and_then(|s| s.succeeded).unwrap_or(0) > 0;
+        if !succeeded && failed_condition(job).is_none() {
+            return Ok(Duration::from_secs(15));
+        }
+        if status.job_name.as_ref() != Some(&job_name) {
+            status.job_name = Some(job_name.clone());
+            if succeeded {
+                let output = pod_output(client.clone(), &namespace, &job_name, None)
+                    .await
+                    .ok_or(anyhow!("No output from netlist export {job_name}"))?;
+                let (file, count) = render_netlist(&output);
+                apply_netlist(client, netlist, file).await?;
+                status.last_sync = Some(Utc::now());
+                status.address_count = count;
+                status.message = None;
+            } else {
+                let logs = job_logs(client.clone(), &namespace, &job_name, 20).await;
+                status.message = Some(format!(
+                    "Export {job_name} failed: {}",
+                    logs.unwrap_or_default()
+                ));
+            }
+        }
+        let started = job
+            .metadata
+            .creation_timestamp
+            .as_ref()
+            .map(|t| t.0)
+            .unwrap_or_default();
+        let next = started + interval - Utc::now();
+        if next > chrono::Duration::zero() {
+            return Ok(next.to_std()?);
+        }
+    }
+
+    let binding = bind_database(client, &namespace, &netlist.spec.database).await?;
+    let databases: Api<OpenDcsDatabase> = Api::namespaced(client.clone(), &namespace);
+    let database = match databases.get_opt(&netlist.spec.database).await? {
+        Some(database) if binding.ready => database,
+        _ => {
+            status.message = binding.waiting_for;
+            return Ok(settings.requeue);
+        }
+    };
+    if database.spec.database_type != DatabaseType::OpenDcsPostgres {
+        status.message = Some(format!(
+            "Netlists can only be exported from {} databases",
+            DatabaseType::OpenDcsPostgres.as_str()
+        ));
+        return Ok(settings.requeue);
+    }
+    let job_name = format!("{}-netlist-{}", name, Utc::now().format("%Y%m%d%H%M%S"));
+    info!("Creating netlist export job {}/{}", &namespace, &job_name);
+    run_job(
+        client,
+        &database,
+        create_export_job(netlist, &database, &job_name),
+    )
+    .await?;
+    Ok(Duration::from_secs(15))
+}
+
+async fn apply_netlist(client: &Client, netlist: &Netlist, file: String) -> Result<()> {
+    let namespace = netlist.namespace().unwrap_or("default".to_string());
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), &namespace);
+    let config_map = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(netlist_config_map_name(&netlist.name_any())),
+            namespace: Some(namespace),
+            owner_references: Some(vec![netlist.controller_owner_ref(&()).unwrap()]),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([(
+            netlist_file_name(&netlist.name_any()),
+            file,
+        )])),
+        ..Default::default()
+    };
+    config_maps
+        .patch(
+            &config_map.name_any(),
+            &PatchParams::apply("apps-controller"),
+            &Patch::Apply(config_map),
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn run(state: State<Netlist>, client: Client) {
+    let settings = state.settings.clone();
+    settings
+        .for_each_namespace(|namespace| run_in(state.clone(), client.clone(), namespace))
+        .await;
+}
+
+async fn run_in(state: State<Netlist>, client: Client, namespace: Option<String>) {
+    let netlists: Api<Netlist> = scoped_api(client.clone(), namespace.as_deref());
+    let jobs: Api<Job> = scoped_api(client.clone(), namespace.as_deref());
+    let config_maps: Api<ConfigMap> = scoped_api(client.clone(), namespace.as_deref());
+    let databases: Api<OpenDcsDatabase> = scoped_api(client.clone(), namespace.as_deref());
+    println!("Starting controller");
+    let controller = Controller::new(netlists, watcher::Config::default())
+        .with_config(state.settings.controller_config());
+    let store = controller.store();
+    controller
+        .owns(jobs, watcher::Config::default())
+        .owns(config_maps, watcher::Config::default())
+        .watches(databases, watcher::Config::default(), move |db| {
+            dependents(&store, &db)
+        })
+        .shutdown_on_signal()
+        .run(reconcile, error_policy, state.to_context(client).await)
+        .filter_map(|x| async move { std::result::Result::ok(x) })
+        .for_each(|_| futures::future::ready(()))
+        .await;
+}
+
+#[instrument(skip(object, ctx), fields(trace_id))]
+async fn reconcile(object: Arc<Netlist>, ctx: Arc<Context<Netlist>>) -> Result<Action, Error> {
+    let trace_id = telemetry::get_trace_id();
+    if trace_id != opentelemetry::trace::TraceId::INVALID {
+        Span::current().record("trace_id", field::display(&trace_id));
+    }
+    let _timer = ctx.metrics.reconcile.count_and_measure(&trace_id);
+    ctx.diagnostics.write().await.last_event = Utc::now();
+    let client = &ctx.client;
+    let name = object.name_any();
+    let ns = object.namespace().unwrap_or("default".to_string());
+    info!("Processing \"{}\" in {}", name, ns);
+    let netlists: Api<Netlist> = Api::namespaced(client.clone(), &ns);
+
+    let mut status = object.status.clone().unwrap_or_default();
+    let requeue = match reconcile_netlist(client, &object, &mut status, &ctx.settings).await {
+        Ok(requeue) => requeue,
+        Err(e) => {
+            error!("Unable to export netlist {}/{}: {:?}", ns, name, e);
+            ctx.metrics.reconcile.set_failure(&object, &e);
+            return Ok(Action::requeue(ctx.settings.error_backoff));
+        }
+    };
+    let current = object.status.clone().map(|s| NetlistStatus {
+        last_updated: None,
+        ..s
+    });
+    if current
+        != Some(NetlistStatus {
+            last_updated: None,
+            ..status.clone()
+        })
+    {
+        status.last_updated = Some(Utc::now());
+        let new_status = Patch::Apply(json!({
+            "apiVersion": "lrgs.opendcs.org/v1",
+            "kind": "Netlist",
+            "status": status
+        }));
+        let pp = PatchParams::apply("apps-controller").force();
+        netlists.patch_status(&name, &pp, &new_status).await?;
+    }
+    Ok(Action::requeue(requeue))
+}
+
+fn error_policy(object: Arc<Netlist>, err: &kube::Error, ctx: Arc<Context<Netlist>>) -> Action {
+    warn!("reconcile failed: {:?}", err);
+    let e = anyhow!("Api error {:?}", err);
+    ctx.metrics.reconcile.set_failure(&object, &e);
+    Action::requeue(ctx.settings.error_backoff)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn export_output_renders_as_netlist() {
+        let output = "\
+psql: notice\n\
+NETLIST|CE1234A6|Mill Creek|Stream gage at the mill\n\
+NETLIST|CE7788B2||\n\
+NETLIST||ignored|no address\n";
+        let (netlist, count) = render_netlist(output);
+        assert_eq!(count, 2);
+        assert_eq!(
+            netlist,
+            "CE1234A6:Mill_Creek Stream gage at the mill\nCE7788B2:\n"
+        );
+    }
+}
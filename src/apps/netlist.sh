@@ -0,0 +1,27 @@
+#!/bin/bash
+set -euo pipefail
+
+# The database secret holds a jdbc uri, psql wants the plain postgresql one.
+PG_URI="${DATABASE_URL#jdbc:}"
+export PGUSER=`cat /secrets/db-admin/username`
+export PGPASSWORD=`cat /secrets/db-admin/password`
+
+# Output is read back by the operator: NETLIST|<address>|<site>|<description>
+psql --dbname="${PG_URI}" --quiet --no-align --tuples-only -v ON_ERROR_STOP=1 \
+    -v sites="${NETLIST_SITES}" \
+    -v agencies="${NETLIST_AGENCIES}" \
+    -v designators="${NETLIST_DESIGNATORS}" <<'SQL'
+SELECT DISTINCT ON (tm.mediumid)
+       concat_ws('|', 'NETLIST', tm.mediumid, coalesce(sn.sitename, ''),
+                 regexp_replace(coalesce(p.description, ''), '[\r\n|]+', ' ', 'g'))
+  FROM platform p
+  JOIN transportmedium tm ON tm.platformid = p.id
+  LEFT JOIN sitename sn ON sn.siteid = p.siteid AND lower(sn.nametype) = 'local'
+ WHERE lower(tm.mediumtype) LIKE 'goes%'
+   AND (:'sites' = '' OR EXISTS (
+        SELECT 1 FROM sitename s
+         WHERE s.siteid = p.siteid AND s.sitename = ANY (string_to_array(:'sites', ','))))
+   AND (:'agencies' = '' OR p.agency = ANY (string_to_array(:'agencies', ',')))
+   AND (:'designators' = '' OR p.platformdesignator = ANY (string_to_array(:'designators', ',')))
+ ORDER BY tm.mediumid;
+SQL
@@ -0,0 +1,261 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    api::{
+        constants::LRGS_GROUP,
+        v1::{
+            lrgs::LrgsCluster,
+            tsdb::{
+                app::AppStatus,
+                database::OpenDcsDatabase,
+                routing::{ROUTING_SCHEDULER_IMAGE, RoutingScheduler},
+            },
+        },
+    },
+    apps::database::{
+        CLIENT_CONFIG_DIR, DatabaseApp, DatabaseBinding, UsesDatabase, app_deployment, apply_app,
+        bind_database, dependents, jvm_env,
+    },
+    lrgs::config::ROUTING_USER,
+    settings::scoped_api,
+    telemetry::{
+        state::{Context, State},
+        telemetry,
+    },
+};
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use futures::StreamExt;
+use k8s_openapi::api::{
+    apps::v1::Deployment,
+    core::v1::{Container, EnvVar, EnvVarSource, Secret, SecretKeySelector, SecurityContext},
+};
+use kube::{
+    Api, Client, Error, ResourceExt,
+    runtime::{Controller, controller::Action, reflector::ObjectRef, watcher},
+};
+use sha2::{Digest, Sha256};
+use tracing::{Span, error, field, info, instrument, warn};
+
+impl UsesDatabase for RoutingScheduler {
+    fn database(&self) -> &str {
+        &self.spec.database
+    }
+}
+
+impl DatabaseApp for RoutingScheduler {
+    fn app_status(&self) -> Option<&AppStatus> {
+        self.status.as_ref()
+    }
+}
+
+/// Check the LrgsCluster and its routing-user are in place. Returns a hash of the routing-user
+/// password so the pods roll when it is rotated.
+async fn bind_lrgs(
+    client: &Client,
+    namespace: &str,
+    scheduler: &RoutingScheduler,
+    binding: &mut DatabaseBinding,
+) -> Result<Option<String>> {
+    let clusters: Api<LrgsCluster> = Api::namespaced(client.clone(), namespace);
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let cluster = &scheduler.spec.lrgs_cluster;
+    let routing_user = if clusters.get_opt(cluster).await?.is_some() {
+        secrets.get_opt(ROUTING_USER).await?
+    } else {
+        None
+    };
+    let Some(password) = routing_user
+        .and_then(|s| s.data)
+        .and_then(|d| d.get("password").cloned())
+    else {
+        binding.ready = false;
+        binding.waiting_for = Some(format!(
+            "Waiting for LrgsCluster {cluster} and its {ROUTING_USER} Secret"
+        ));
+        return Ok(None);
+    };
+    let hash = Sha256::digest(&password.0);
+    Ok(Some(base16ct::lower::encode_string(&hash)))
+}
+
+fn routing_user_env(name: &str, key: &str) -> EnvVar {
+    EnvVar {
+        name: name.to_string(),
+        value_from: Some(EnvVarSource {
+            secret_key_ref: Some(SecretKeySelector {
+                name: ROUTING_USER.to_string(),
+                key: key.to_string(),
+                optional: Some(false),
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+pub fn create_deployment(
+    scheduler: &RoutingScheduler,
+    binding: &DatabaseBinding,
+    routing_user_hash: Option<String>,
+) -> Deployment {
+    let namespace = scheduler.namespace().unwrap_or("default".to_string());
+    let mut env = vec![
+        EnvVar {
+            name: "LRGS_HOST".to_string(),
+            value: Some(format!(
+                "{}-lrgs-service.{}.svc",
+                scheduler.spec.lrgs_cluster, namespace
+            )),
+            ..Default::default()
+        },
+        EnvVar {
+            name: "LRGS_PORT".to_string(),
+            value: Some("16003".to_string()),
+            ..Default::default()
+        },
+        routing_user_env("LRGS_USERNAME", "username"),
+        routing_user_env("LRGS_PASSWORD", "password"),
+    ];
+    env.extend(jvm_env(&scheduler.spec.jvm_options));
+    let container = Container {
+        name: "routsched".to_string(),
+        image: Some(
+            scheduler
+                .spec
+                .image
+                .clone()
+                .unwrap_or(ROUTING_SCHEDULER_IMAGE.to_string()),
+        ),
+        command: Some(vec![
+            "routsched".to_string(),
+            "-a".to_string(),
+            scheduler.spec.app_name.clone(),
+            "-P".to_string(),
+            format!("{CLIENT_CONFIG_DIR}/decodes.properties"),
+            "-l".to_string(),
+            "/dev/stdout".to_string(),
+        ]),
+        env: Some(env),
+        resources: scheduler.spec.resources.clone(),
+        security_context: Some(SecurityContext {
+            allow_privilege_escalation: Some(false),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let mut annotations = BTreeMap::new();
+    if let Some(hash) = routing_user_hash {
+        annotations.insert(format!("{}/routing-user-hash", LRGS_GROUP.as_str()), hash);
+    }
+    app_deployment(
+        scheduler,
+        "routingscheduler",
+        binding,
+        1,
+        container,
+        annotations,
+        &scheduler.spec.image_pull_secrets,
+    )
+}
+
+pub async fn run(state: State<RoutingScheduler>, client: Client) {
+    let settings = state.settings.clone();
+    settings
+        .for_each_namespace(|namespace| run_in(state.clone(), client.clone(), namespace))
+        .await;
+}
+
+async fn run_in(state: State<RoutingScheduler>, client: Client, namespace: Option<String>) {
+    let schedulers: Api<RoutingScheduler> = scoped_api(client.clone(), namespace.as_deref());
+    let deployments: Api<Deployment> = scoped_api(client.clone(), namespace.as_deref());
+    let databases: Api<OpenDcsDatabase> = scoped_api(client.clone(), namespace.as_deref());
+    let clusters: Api<LrgsCluster> = scoped_api(client.clone(), namespace.as_deref());
+    println!("Starting controller");
+    let controller = Controller::new(schedulers, watcher::Config::default())
+        .with_config(state.settings.controller_config());
+    let store = controller.store();
+    let database_store = store.clone();
+    controller
+        .owns(deployments, watcher::Config::default())
+        .watches(databases, watcher::Config::default(), move |db| {
+            dependents(&database_store, &db)
+        })
+        .watches(
+            clusters,
+            watcher::Config::default(),
+            move |cluster: LrgsCluster| {
+                store
+                    .state()
+                    .into_iter()
+                    .filter(|s| {
+                        s.spec.lrgs_cluster == cluster.name_any()
+                            && s.namespace() == cluster.namespace()
+                    })
+                    .map(|s| ObjectRef::from_obj(&*s))
+                    .collect::<Vec<_>>()
+            },
+        )
+        .shutdown_on_signal()
+        .run(reconcile, error_policy, state.to_context(client).await)
+        .filter_map(|x| async move { std::result::Result::ok(x) })
+        .for_each(|_| futures::future::ready(()))
+        .await;
+}
+
+#[instrument(skip(object, ctx), fields(trace_id))]
+async fn reconcile(
+    object: Arc<RoutingScheduler>,
+    ctx: Arc<Context<RoutingScheduler>>,
+) -> Result<Action, Error> {
+    let trace_id = telemetry::get_trace_id();
+    if trace_id != opentelemetry::trace::TraceId::INVALID {
+        Span::current().record("trace_id", field::display(&trace_id));
+    }
+    let _timer = ctx.metrics.reconcile.count_and_measure(&trace_id);
+    ctx.diagnostics.write().await.last_event = Utc::now();
+    let client = &ctx.client;
+    let name = object.name_any();
+    let ns = object.namespace().unwrap_or("default".to_string());
+    info!("Processing \"{}\" in {}", name, ns);
+
+    let mut binding = match bind_database(client, &ns, &object.spec.database).await {
+        Ok(binding) => binding,
+        Err(e) => {
+            error!(
+                "Unable to look up database {} for {}/{}: {:?}",
+                object.spec.database, ns, name, e
+            );
+            ctx.metrics.reconcile.set_failure(&object, &e);
+            return Ok(Action::requeue(ctx.settings.error_backoff));
+        }
+    };
+    let routing_user_hash = match bind_lrgs(client, &ns, &object, &mut binding).await {
+        Ok(hash) => hash,
+        Err(e) => {
+            error!(
+                "Unable to look up LrgsCluster {} for {}/{}: {:?}",
+                object.spec.lrgs_cluster, ns, name, e
+            );
+            ctx.metrics.reconcile.set_failure(&object, &e);
+            return Ok(Action::requeue(ctx.settings.error_backoff));
+        }
+    };
+    if let Some(waiting_for) = &binding.waiting_for {
+        info!("{}/{} stays scaled down: {}", ns, name, waiting_for);
+    }
+    let deployment = create_deployment(&object, &binding, routing_user_hash);
+    apply_app(client, &*object, &binding, deployment).await?;
+    Ok(Action::requeue(ctx.settings.requeue))
+}
+
+fn error_policy(
+    object: Arc<RoutingScheduler>,
+    err: &kube::Error,
+    ctx: Arc<Context<RoutingScheduler>>,
+) -> Action {
+    warn!("reconcile failed: {:?}", err);
+    let e = anyhow!("Api error {:?}", err);
+    ctx.metrics.reconcile.set_failure(&object, &e);
+    Action::requeue(ctx.settings.error_backoff)
+}
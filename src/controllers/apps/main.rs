@@ -0,0 +1,16 @@
+use clap::Parser;
+use opendcs_controllers::operator::{self, ControllerKind};
+use opendcs_controllers::settings::ControllerArgs;
+
+/// Controllers for the OpenDCS applications running against a database.
+#[derive(Parser, Debug)]
+struct Args {
+    #[command(flatten)]
+    controller: ControllerArgs,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    operator::run(&[ControllerKind::Apps], args.controller).await
+}
@@ -0,0 +1,8 @@
+use clap::Parser;
+use opendcs_controllers::operator::{self, OperatorArgs};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = OperatorArgs::parse();
+    operator::run(&args.controllers, args.controller).await
+}
@@ -1,7 +1,5 @@
-use api::v1;
 use kube::CustomResourceExt;
-
-mod api;
+use opendcs_controllers::api::v1;
 
 fn main() {
     println!("---");
@@ -20,8 +18,33 @@ fn main() {
         serde_yaml::to_string(&v1::lrgs::LrgsCluster::crd()).unwrap()
     );
     println!("---");
+    print!(
+        "{}",
+        serde_yaml::to_string(&v1::netlist::Netlist::crd()).unwrap()
+    );
+    println!("---");
     print!(
         "{}",
         serde_yaml::to_string(&v1::tsdb::database::OpenDcsDatabase::crd()).unwrap()
     );
+    println!("---");
+    print!(
+        "{}",
+        serde_yaml::to_string(&v1::tsdb::computation::ComputationProcess::crd()).unwrap()
+    );
+    println!("---");
+    print!(
+        "{}",
+        serde_yaml::to_string(&v1::tsdb::routing::RoutingScheduler::crd()).unwrap()
+    );
+    println!("---");
+    print!(
+        "{}",
+        serde_yaml::to_string(&v1::tsdb::api::OpenDcsApi::crd()).unwrap()
+    );
+    println!("---");
+    print!(
+        "{}",
+        serde_yaml::to_string(&v1::tsdb::import::DecodesImport::crd()).unwrap()
+    );
 }
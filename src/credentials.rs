@@ -0,0 +1,72 @@
+use chrono::{DateTime, Duration, Utc};
+use passwords::PasswordGenerator;
+
+use crate::api::v1::rotation::RotationPolicy;
+
+/// Generate a password for an operator managed user.
+pub fn generate_password() -> String {
+    PasswordGenerator {
+        length: 64,
+        numbers: true,
+        lowercase_letters: true,
+        uppercase_letters: true,
+        symbols: false,
+        spaces: false,
+        exclude_similar_characters: false,
+        strict: true,
+    }
+    .generate_one()
+    .unwrap()
+}
+
+/// Whether credentials should be rotated now. Either the rotate annotation holds a value
+/// that hasn't been acted on yet, or the policy interval has passed since the last rotation.
+pub fn rotation_due(
+    policy: Option<&RotationPolicy>,
+    last_rotated: Option<DateTime<Utc>>,
+    requested: Option<&String>,
+    last_requested: Option<&String>,
+) -> bool {
+    if requested.is_some() && requested != last_requested {
+        return true;
+    }
+    match (policy, last_rotated) {
+        (Some(policy), Some(last_rotated)) => {
+            Utc::now() - last_rotated >= Duration::days(policy.interval_days.into())
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn generated_passwords_are_unique() {
+        let password = generate_password();
+        assert_eq!(password.len(), 64);
+        assert_ne!(password, generate_password());
+    }
+
+    #[test]
+    fn rotation_follows_the_policy_interval() {
+        let policy = RotationPolicy { interval_days: 30 };
+        let recent = Utc::now() - Duration::days(1);
+        let old = Utc::now() - Duration::days(31);
+        assert!(!rotation_due(Some(&policy), Some(recent), None, None));
+        assert!(rotation_due(Some(&policy), Some(old), None, None));
+        assert!(!rotation_due(None, Some(old), None, None));
+        // the first rotation is only scheduled once a baseline is recorded
+        assert!(!rotation_due(Some(&policy), None, None, None));
+    }
+
+    #[test]
+    fn rotation_is_requested_once_per_annotation_value() {
+        let first = "1".to_string();
+        let second = "2".to_string();
+        assert!(rotation_due(None, None, Some(&first), None));
+        assert!(!rotation_due(None, None, Some(&first), Some(&first)));
+        assert!(rotation_due(None, None, Some(&second), Some(&first)));
+    }
+}
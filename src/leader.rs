@@ -0,0 +1,269 @@
+//! Lease based leader election, so only one replica of a controller reconciles at a time.
+
+use std::{future::Future, sync::Arc, time::Duration};
+
+use anyhow::{Result, bail};
+use chrono::Utc;
+use k8s_openapi::{
+    api::coordination::v1::{Lease, LeaseSpec},
+    apimachinery::pkg::apis::meta::v1::MicroTime,
+};
+use kube::{
+    Api, Client,
+    api::{ObjectMeta, PostParams},
+};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::telemetry::state::Diagnostics;
+
+/// How long a lease is held without renewal before another replica may take it.
+const LEASE_DURATION: Duration = Duration::from_secs(15);
+/// How long the leader keeps leading without a successful renewal. Shorter than
+/// `LEASE_DURATION`, so it stops before a standby may take the lease.
+const RENEW_DEADLINE: Duration = Duration::from_secs(10);
+/// How often the leader renews and standbys retry.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What a replica should do with the lease as it is now.
+#[derive(Debug, PartialEq)]
+enum Claim {
+    /// Held by this replica.
+    Renew,
+    /// Missing, released or expired.
+    Acquire,
+    /// Held by the named replica.
+    Standby(String),
+}
+
+fn claim(lease: Option<&Lease>, identity: &str, now: chrono::DateTime<Utc>) -> Claim {
+    let Some(spec) = lease.and_then(|l| l.spec.as_ref()) else {
+        return Claim::Acquire;
+    };
+    let Some(holder) = spec.holder_identity.as_ref().filter(|h| !h.is_empty()) else {
+        return Claim::Acquire;
+    };
+    if holder == identity {
+        return Claim::Renew;
+    }
+    let duration = chrono::Duration::seconds(spec.lease_duration_seconds.unwrap_or(0) as i64);
+    match spec.renew_time.as_ref().or(spec.acquire_time.as_ref()) {
+        Some(renewed) if renewed.0 + duration > now => Claim::Standby(holder.clone()),
+        _ => Claim::Acquire,
+    }
+}
+
+/// Resolves on SIGINT or SIGTERM, the signals the controllers shut down on.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Unable to listen for SIGTERM: {:?}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+pub struct LeaderElection {
+    leases: Api<Lease>,
+    lease_name: String,
+    identity: String,
+    diagnostics: Vec<Arc<RwLock<Diagnostics>>>,
+}
+
+impl LeaderElection {
+    /// Election on the named Lease in the namespace of the pod, POD_NAMESPACE when set.
+    /// Replicas are told apart by POD_NAME, or the hostname.
+    pub fn new(client: Client, lease_name: &str) -> Self {
+        let namespace = std::env::var("POD_NAMESPACE")
+            .unwrap_or_else(|_| client.default_namespace().to_string());
+        let identity = std::env::var("POD_NAME")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| format!("{lease_name}-{}", std::process::id()));
+        Self {
+            leases: Api::namespaced(client, &namespace),
+            lease_name: lease_name.to_string(),
+            identity,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Diagnostics to show the current leader in.
+    pub fn with_diagnostics(mut self, diagnostics: Arc<RwLock<Diagnostics>>) -> Self {
+        self.diagnostics.push(diagnostics);
+        self
+    }
+
+    async fn show_leader(&self, leader: Option<String>) {
+        for diagnostics in &self.diagnostics {
+            diagnostics.write().await.leader = leader.clone();
+        }
+    }
+
+    /// Try to take or renew the lease, returning whether this replica holds it.
+    async fn try_lead(&self) -> Result<bool> {
+        let now = Utc::now();
+        let current = self.leases.get_opt(&self.lease_name).await?;
+        let lease = match claim(current.as_ref(), &self.identity, now) {
+            Claim::Standby(holder) => {
+                self.show_leader(Some(holder)).await;
+                return Ok(false);
+            }
+            Claim::Renew => {
+                let mut lease = current.unwrap();
+                let spec = lease.spec.get_or_insert_with(Default::default);
+                spec.renew_time = Some(MicroTime(now));
+                spec.lease_duration_seconds = Some(LEASE_DURATION.as_secs() as i32);
+                lease
+            }
+            Claim::Acquire => {
+                let transitions = current
+                    .as_ref()
+                    .map(|l| {
+                        l.spec
+                            .as_ref()
+                            .and_then(|s| s.lease_transitions)
+                            .unwrap_or(0)
+                            + 1
+                    })
+                    .unwrap_or(0);
+                Lease {
+                    metadata: current.map(|l| l.metadata).unwrap_or(ObjectMeta {
+                        name: Some(self.lease_name.clone()),
+                        ..Default::default()
+                    }),
+                    spec: Some(LeaseSpec {
+                        holder_identity: Some(self.identity.clone()),
+                        lease_duration_seconds: Some(LEASE_DURATION.as_secs() as i32),
+                        acquire_time: Some(MicroTime(now)),
+                        renew_time: Some(MicroTime(now)),
+                        lease_transitions: Some(transitions),
+                    }),
+                }
+            }
+        };
+        // The resource version makes concurrent claims conflict, only one of them succeeds.
+        let written = if lease.metadata.resource_version.is_some() {
+            self.leases
+                .replace(&self.lease_name, &PostParams::default(), &lease)
+                .await
+        } else {
+            self.leases.create(&PostParams::default(), &lease).await
+        };
+        match written {
+            Ok(_) => {
+                self.show_leader(Some(self.identity.clone())).await;
+                Ok(true)
+            }
+            Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Give the lease up so a standby can take over without waiting for it to expire.
+    async fn release(&self) -> Result<()> {
+        if let Some(mut lease) = self.leases.get_opt(&self.lease_name).await? {
+            let spec = lease.spec.get_or_insert_with(Default::default);
+            if spec.holder_identity.as_ref() == Some(&self.identity) {
+                spec.holder_identity = None;
+                self.leases
+                    .replace(&self.lease_name, &PostParams::default(), &lease)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until this replica leads, then run `controller` while renewing the lease. Fails
+    /// if the lease is lost, the process should exit so it restarts as a standby. A standby
+    /// stops waiting on a shutdown signal.
+    pub async fn run<F: Future<Output = ()>>(self, controller: F) -> Result<()> {
+        info!("Waiting to lead {} as {}", &self.lease_name, &self.identity);
+        let acquire = async {
+            loop {
+                match self.try_lead().await {
+                    Ok(true) => break,
+                    Ok(false) => {}
+                    Err(e) => warn!("Unable to claim lease {}: {:?}", &self.lease_name, e),
+                }
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+        };
+        tokio::select! {
+            _ = acquire => {}
+            _ = shutdown_signal() => {
+                info!("Shutting down while waiting to lead {}", &self.lease_name);
+                return Ok(());
+            }
+        }
+        info!("Leading {} as {}", &self.lease_name, &self.identity);
+
+        let renew = async {
+            let mut last_renewed = tokio::time::Instant::now();
+            loop {
+                tokio::time::sleep(RETRY_INTERVAL).await;
+                // A hanging request must not hold off the deadline either.
+                let remaining = RENEW_DEADLINE.saturating_sub(last_renewed.elapsed());
+                match tokio::time::timeout(remaining, self.try_lead()).await {
+                    Ok(Ok(true)) => last_renewed = tokio::time::Instant::now(),
+                    Ok(Ok(false)) => return,
+                    Ok(Err(e)) => warn!("Unable to renew lease {}: {:?}", &self.lease_name, e),
+                    Err(_) => warn!("Renewing lease {} timed out", &self.lease_name),
+                }
+                if last_renewed.elapsed() >= RENEW_DEADLINE {
+                    return;
+                }
+            }
+        };
+        tokio::select! {
+            _ = controller => {
+                self.release().await?;
+                Ok(())
+            }
+            _ = renew => bail!("Lost lease {}", &self.lease_name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lease(holder: Option<&str>, renewed_seconds_ago: i64) -> Lease {
+        Lease {
+            spec: Some(LeaseSpec {
+                holder_identity: holder.map(str::to_string),
+                lease_duration_seconds: Some(15),
+                renew_time: Some(MicroTime(
+                    Utc::now() - chrono::Duration::seconds(renewed_seconds_ago),
+                )),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lease_is_taken_only_when_free_or_expired() {
+        let now = Utc::now();
+        assert_eq!(claim(None, "a", now), Claim::Acquire);
+        assert_eq!(claim(Some(&lease(None, 0)), "a", now), Claim::Acquire);
+        assert_eq!(claim(Some(&lease(Some("a"), 0)), "a", now), Claim::Renew);
+        assert_eq!(
+            claim(Some(&lease(Some("b"), 5)), "a", now),
+            Claim::Standby("b".to_string())
+        );
+        assert_eq!(claim(Some(&lease(Some("b"), 60)), "a", now), Claim::Acquire);
+    }
+}
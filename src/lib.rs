@@ -1,4 +1,10 @@
 pub mod api;
+pub mod apps;
+pub mod credentials;
+pub mod leader;
 pub mod lrgs;
+pub mod operator;
+pub mod render;
 pub mod schema;
+pub mod settings;
 pub mod telemetry;
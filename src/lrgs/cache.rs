@@ -0,0 +1,257 @@
+use std::{future::Future, sync::Arc};
+
+use anyhow::Result;
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::Secret;
+use kube::{
+    Api, Client, Resource, ResourceExt,
+    runtime::{
+        WatchStreamExt,
+        reflector::{self, ObjectRef, ReflectHandle, Store},
+        watcher,
+    },
+};
+use serde::de::DeserializeOwned;
+use tracing::warn;
+
+use crate::{
+    api::v1::{dds_recv::DdsConnection, drgs::DrgsConnection, lrgs::LrgsCluster, netlist::Netlist},
+    lrgs::config::{DDS_USER_TYPE, LrgsInputs},
+    settings::scoped_api,
+};
+
+/// Events buffered for each subscriber of a shared reflector.
+const SUBSCRIBER_BUFFER: usize = 256;
+
+/// Reflector stores of everything the configuration of an LrgsCluster is rendered from, so
+/// reconciles read from memory instead of listing on every pass.
+#[derive(Clone)]
+pub struct LrgsCaches {
+    pub dds_connections: Store<DdsConnection>,
+    pub drgs_connections: Store<DrgsConnection>,
+    /// Secrets of type lrgs.opendcs.org/ddsuser.
+    pub users: Store<Secret>,
+    pub netlists: Store<Netlist>,
+}
+
+/// Streams of changes to the cached kinds, for mapping to the affected clusters.
+pub struct LrgsSubscribers {
+    pub dds_connections: ReflectHandle<DdsConnection>,
+    pub drgs_connections: ReflectHandle<DrgsConnection>,
+    pub users: ReflectHandle<Secret>,
+    pub netlists: ReflectHandle<Netlist>,
+}
+
+/// Objects of a store in `namespace`, ordered by name like a list call.
+fn in_namespace<K>(store: &Store<K>, namespace: &str) -> Vec<K>
+where
+    K: Resource + Clone + 'static,
+    K::DynamicType: Eq + std::hash::Hash + Clone,
+{
+    let mut objects: Vec<K> = store
+        .state()
+        .iter()
+        .filter(|o| o.namespace().as_deref() == Some(namespace))
+        .map(|o| K::clone(o))
+        .collect();
+    objects.sort_by_key(|o| o.name_any());
+    objects
+}
+
+impl LrgsCaches {
+    /// Inputs for the configuration of the clusters in `namespace`.
+    pub fn inputs(&self, namespace: &str) -> LrgsInputs {
+        LrgsInputs {
+            dds_connections: in_namespace(&self.dds_connections, namespace),
+            drgs_connections: in_namespace(&self.drgs_connections, namespace),
+            users: in_namespace(&self.users, namespace),
+        }
+    }
+
+    pub fn user(&self, namespace: &str, name: &str) -> Option<Arc<Secret>> {
+        self.users.get(&ObjectRef::new(name).within(namespace))
+    }
+
+    /// Names of the Netlists provided to a cluster.
+    pub fn netlists_for(&self, cluster: &LrgsCluster) -> Vec<String> {
+        let namespace = cluster.namespace().unwrap_or("default".to_string());
+        in_namespace(&self.netlists, &namespace)
+            .into_iter()
+            .filter(|n| n.spec.lrgs_cluster == cluster.name_any())
+            .map(|n| n.name_any())
+            .collect()
+    }
+
+    pub async fn wait_until_ready(&self) -> Result<()> {
+        self.dds_connections.wait_until_ready().await?;
+        self.drgs_connections.wait_until_ready().await?;
+        self.users.wait_until_ready().await?;
+        self.netlists.wait_until_ready().await?;
+        Ok(())
+    }
+}
+
+/// Clusters in the namespace of a changed object. Every cluster in a namespace reads all the
+/// connections and users of that namespace.
+pub fn clusters_in_namespace<K: Resource>(
+    clusters: &Store<LrgsCluster>,
+    object: &K,
+) -> Vec<ObjectRef<LrgsCluster>> {
+    clusters
+        .state()
+        .iter()
+        .filter(|c| c.namespace() == object.meta().namespace)
+        .map(|c| ObjectRef::from_obj(&**c))
+        .collect()
+}
+
+fn shared<K>(
+    api: Api<K>,
+    config: watcher::Config,
+) -> (
+    Store<K>,
+    ReflectHandle<K>,
+    impl Future<Output = ()> + Send + 'static,
+)
+where
+    K: Resource + Clone + DeserializeOwned + std::fmt::Debug + Send + Sync + 'static,
+    K::DynamicType: Default + Eq + std::hash::Hash + Clone + Send + Sync,
+{
+    let (store, writer) = reflector::store_shared(SUBSCRIBER_BUFFER);
+    let subscriber = writer
+        .subscribe()
+        .expect("subscribers can only be created from shared stores");
+    let reflector = watcher(api, config)
+        .default_backoff()
+        .reflect_shared(writer)
+        .for_each(|event| async move {
+            if let Err(error) = event {
+                warn!("cache watch failed: {:?}", error);
+            }
+        });
+    (store, subscriber, reflector)
+}
+
+/// Start the reflectors over one namespace, or all of them for `None`. The returned future
+/// drives them and has to be polled alongside the controller.
+pub fn reflectors(
+    client: Client,
+    namespace: Option<&str>,
+) -> (
+    LrgsCaches,
+    LrgsSubscribers,
+    impl Future<Output = ()> + Send + 'static,
+) {
+    let (dds_connections, dds_subscriber, dds_reflector) = shared(
+        scoped_api(client.clone(), namespace),
+        watcher::Config::default(),
+    );
+    let (drgs_connections, drgs_subscriber, drgs_reflector) = shared(
+        scoped_api(client.clone(), namespace),
+        watcher::Config::default(),
+    );
+    let (users, user_subscriber, user_reflector) = shared(
+        scoped_api(client.clone(), namespace),
+        watcher::Config::default().fields(&format!("type={DDS_USER_TYPE}")),
+    );
+    let (netlists, netlist_subscriber, netlist_reflector) =
+        shared(scoped_api(client, namespace), watcher::Config::default());
+    let reflectors = async move {
+        tokio::join!(
+            dds_reflector,
+            drgs_reflector,
+            user_reflector,
+            netlist_reflector
+        );
+    };
+    (
+        LrgsCaches {
+            dds_connections,
+            drgs_connections,
+            users,
+            netlists,
+        },
+        LrgsSubscribers {
+            dds_connections: dds_subscriber,
+            drgs_connections: drgs_subscriber,
+            users: user_subscriber,
+            netlists: netlist_subscriber,
+        },
+        reflectors,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn store<K>(objects: Vec<serde_json::Value>) -> Store<K>
+    where
+        K: Resource + Clone + DeserializeOwned,
+        K::DynamicType: Default + Eq + std::hash::Hash + Clone,
+    {
+        let (store, mut writer) = reflector::store();
+        for object in objects {
+            let object = serde_json::from_value(object).unwrap();
+            writer.apply_watcher_event(&watcher::Event::Apply(object));
+        }
+        store
+    }
+
+    fn connection(name: &str, namespace: &str) -> serde_json::Value {
+        json!({
+            "apiVersion": "lrgs.opendcs.org/v1",
+            "kind": "DdsConnection",
+            "metadata": {"name": name, "namespace": namespace},
+            "spec": {"hostname": "upstream.test", "username": "testuser"}
+        })
+    }
+
+    fn cluster(name: &str, namespace: &str) -> serde_json::Value {
+        json!({
+            "apiVersion": "lrgs.opendcs.org/v1",
+            "kind": "LrgsCluster",
+            "metadata": {"name": name, "namespace": namespace},
+            "spec": {"replicas": 1, "storageClass": "standard", "storageSize": "1Gi"}
+        })
+    }
+
+    #[test]
+    fn changes_map_to_clusters_in_the_same_namespace() {
+        let clusters: Store<LrgsCluster> = store(vec![
+            cluster("main", "lrgs"),
+            cluster("backup", "lrgs"),
+            cluster("main", "other"),
+        ]);
+        let connection: DdsConnection =
+            serde_json::from_value(connection("upstream", "lrgs")).unwrap();
+        let mut affected: Vec<String> = clusters_in_namespace(&clusters, &connection)
+            .into_iter()
+            .map(|c| format!("{}/{}", c.namespace.unwrap(), c.name))
+            .collect();
+        affected.sort();
+        assert_eq!(affected, vec!["lrgs/backup", "lrgs/main"]);
+    }
+
+    #[test]
+    fn inputs_are_ordered_by_name() {
+        let caches = LrgsCaches {
+            dds_connections: store(vec![
+                connection("zulu", "lrgs"),
+                connection("alpha", "lrgs"),
+                connection("other", "other"),
+            ]),
+            drgs_connections: store(vec![]),
+            users: store(vec![]),
+            netlists: store(vec![]),
+        };
+        let names: Vec<String> = caches
+            .inputs("lrgs")
+            .dds_connections
+            .iter()
+            .map(|c| c.name_any())
+            .collect();
+        assert_eq!(names, vec!["alpha", "zulu"]);
+    }
+}
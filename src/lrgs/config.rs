@@ -1,38 +1,34 @@
-use crate::api::{
-    constants::LRGS_GROUP,
-    v1::{
-        dds_recv::{DdsConnection, TlsMode},
-        drgs::DrgsConnection,
-        lrgs::LrgsCluster,
+use crate::{
+    api::{
+        constants::LRGS_GROUP,
+        v1::{
+            dds_recv::{DdsConnection, TlsMode},
+            drgs::DrgsConnection,
+            lrgs::LrgsCluster,
+        },
     },
+    credentials::{generate_password, rotation_due},
 };
 use k8s_openapi::{
     ByteString, api::core::v1::Secret, apimachinery::pkg::apis::meta::v1::OwnerReference,
 };
-use kube::{
-    Client,
-    api::{Api, ListParams, ObjectMeta},
-};
-use passwords::PasswordGenerator;
+use kube::{ResourceExt, api::ObjectMeta};
 use sha2::{Digest, Sha256};
 use simple_xml_builder::XMLElement;
 use tracing::debug;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use std::{collections::BTreeMap, vec};
 
 use super::password_file;
 
-fn add_dds_connection(
-    conf: &mut XMLElement,
-    i: i32,
-    name: &str,
-    hostname: &str,
-    port: i32,
-    username: &str,
-    enabled: bool,
-    tls_mode: TlsMode,
-) {
+fn add_dds_connection(conf: &mut XMLElement, i: i32, host: &DdsConnection) {
+    let name = host.name_any();
+    let hostname = &host.spec.hostname;
+    let port = host.spec.port;
+    let username = &host.spec.username;
+    let enabled = host.spec.enabled.unwrap_or(false);
+    let tls_mode = host.spec.tls_mode.clone().unwrap_or(TlsMode::NoTls);
     let mut connection = XMLElement::new("connection");
     connection.add_attribute("number", i);
     connection.add_attribute("host", hostname);
@@ -68,37 +64,21 @@ fn add_dds_connection(
     conf.add_child(connection);
 }
 
-async fn create_ddsrecv_conf(client: Client, namespace: &str) -> Result<String> {
+/// DdsConnections rendered as ddsrecv.conf.
+pub fn render_ddsrecv_conf(connections: &[DdsConnection]) -> String {
     let mut ddsrecv_conf = XMLElement::new("ddsrecvconf");
-    let mut i: i32 = 0;
-    // Read pods in the configured namespace into the typed interface from k8s-openapi
-    let connections: Api<DdsConnection> = Api::namespaced(client.clone(), &namespace);
-
-    // NOTE: review error handling more. No connections is reasonable, need
-    // to make sure this would always just be empty and figure out some other error conditions.
-    for host in connections.list(&ListParams::default()).await? {
-        println!("found dds {}", host.spec.hostname);
-        add_dds_connection(
-            &mut ddsrecv_conf,
-            i,
-            &host.metadata.name.unwrap(),
-            &host.spec.hostname,
-            host.spec.port,
-            &host.spec.username,
-            host.spec.enabled.unwrap_or(false),
-            host.spec.tls_mode.unwrap_or(TlsMode::NoTls),
-        );
-        i = i + 1;
+    for (i, host) in (0_i32..).zip(connections) {
+        debug!("found dds {}", host.spec.hostname);
+        add_dds_connection(&mut ddsrecv_conf, i, host);
     }
-    Ok(ddsrecv_conf.to_string())
+    ddsrecv_conf.to_string()
 }
 
-async fn create_drgsrecv_conf(client: Client, namespace: &str) -> Result<String> {
+/// DrgsConnections rendered as drgsconf.xml.
+pub fn render_drgsrecv_conf(connections: &[DrgsConnection]) -> String {
     let mut drgsrecv_conf = XMLElement::new("drgsconf");
-    let mut i: i32 = 0;
-    let drgs_connections: Api<DrgsConnection> = Api::namespaced(client.clone(), namespace);
-    for connection in drgs_connections.list(&ListParams::default()).await? {
-        println!("Adding DRGS Connection {i}: {}", connection.spec.hostname);
+    for (i, connection) in (0_i32..).zip(connections.iter().cloned()) {
+        debug!("Adding DRGS Connection {i}: {}", connection.spec.hostname);
         let mut xml_connection = XMLElement::new("connection");
         xml_connection.add_attribute("number", i);
         xml_connection.add_attribute("host", connection.spec.hostname);
@@ -128,27 +108,26 @@ async fn create_drgsrecv_conf(client: Client, namespace: &str) -> Result<String>
         xml_connection.add_child(xml_event_port_enabled);
         xml_connection.add_child(xml_start_pattern);
         drgsrecv_conf.add_child(xml_connection);
-
-        i = i + 1;
     }
-    Ok(drgsrecv_conf.to_string())
+    drgsrecv_conf.to_string()
 }
 
-async fn create_password_file(client: Client, namespace: &str) -> Result<String> {
-    let users: Api<Secret> = Api::namespaced(client.clone(), namespace);
-    let params = ListParams::default().fields("type=lrgs.opendcs.org/ddsuser");
+/// DDS user Secrets rendered as .lrgs.passwd.
+pub fn render_password_file(users: &[Secret]) -> Result<String> {
     let mut pw_file = password_file::PasswordFile::new();
-    for user in users.list(&params).await? {
-        let data = user.data;
-        if data.is_some() {
-            let data = data.unwrap();
-            let username = String::from_utf8(data.get("username").unwrap().0.clone())?;
-            let password = String::from_utf8(data.get("password").unwrap().0.clone())?;
+    for user in users {
+        if let Some(data) = &user.data {
+            let field = |key: &str| {
+                data.get(key)
+                    .ok_or_else(|| anyhow!("Secret {} has no {key}", user.name_any()))
+            };
+            let username = String::from_utf8(field("username")?.0.clone())?;
+            let password = String::from_utf8(field("password")?.0.clone())?;
             let roles = data.get("roles");
             let roles = match roles {
                 Some(_) => String::from_utf8(roles.unwrap().0.clone())?
                     .split(",")
-                    .map(|i| String::from(i))
+                    .map(String::from)
                     .collect(),
                 None => vec![],
             };
@@ -159,34 +138,55 @@ async fn create_password_file(client: Client, namespace: &str) -> Result<String>
             });
         }
     }
-    Ok(pw_file.to_string())
+    Ok(pw_file.contents())
+}
+
+/// Secret type marking DDS users.
+pub const DDS_USER_TYPE: &str = "lrgs.opendcs.org/ddsuser";
+
+/// Everything in the namespace the configuration of an LrgsCluster is rendered from.
+#[derive(Clone, Debug, Default)]
+pub struct LrgsInputs {
+    pub dds_connections: Vec<DdsConnection>,
+    pub drgs_connections: Vec<DrgsConnection>,
+    /// Secrets of type lrgs.opendcs.org/ddsuser.
+    pub users: Vec<Secret>,
 }
 
+/// Configuration file LRGS only reads at startup.
+pub const LRGS_CONF: &str = "lrgs.conf";
+
 pub struct LrgsConfig {
     pub secret: Secret,
+    /// Hash over every file, recorded in the cluster status.
     pub hash: String,
+    /// Hash of each file in the secret. The password file and receiver configs are reloaded
+    /// by lrgs.sh while LRGS runs.
+    pub file_hashes: BTreeMap<String, String>,
+}
+
+impl LrgsConfig {
+    /// Hash of the files a running LRGS does not pick up, stamped on the pod template so a
+    /// change rolls the pods.
+    pub fn restart_hash(&self) -> String {
+        self.file_hashes.get(LRGS_CONF).cloned().unwrap_or_default()
+    }
 }
 
-pub async fn create_lrgs_config(
-    client: Client,
+pub fn render_lrgs_config(
     cluster: &LrgsCluster,
     owner_ref: &OwnerReference,
+    inputs: &LrgsInputs,
 ) -> Result<LrgsConfig> {
-    let mut hasher = Sha256::new();
     let namespace = cluster
         .metadata
         .namespace
         .clone()
         .expect("LrgsCluster does not have a namespace set.");
 
-    let password_file = create_password_file(client.clone(), &namespace).await?;
-    hasher.update(password_file.as_bytes());
-
-    let dds_config = create_ddsrecv_conf(client.clone(), &namespace).await?;
-    hasher.update(dds_config.as_bytes());
-
-    let drgs_config = create_drgsrecv_conf(client.clone(), &namespace).await?;
-    hasher.update(drgs_config.as_bytes());
+    let password_file = render_password_file(&inputs.users)?;
+    let dds_config = render_ddsrecv_conf(&inputs.dds_connections);
+    let drgs_config = render_drgsrecv_conf(&inputs.drgs_connections);
 
     let num_day_files = cluster.spec.archive_length_days.unwrap_or(31);
 
@@ -213,13 +213,27 @@ noTimeout: true
     let dds_config_data = Vec::from(dds_config);
     let drgs_config_data = Vec::from(drgs_config);
 
+    let data = BTreeMap::from([
+        (".lrgs.passwd".to_string(), ByteString(password_file_data)),
+        ("ddsrecv.conf".to_string(), ByteString(dds_config_data)),
+        ("drgsconf.xml".to_string(), ByteString(drgs_config_data)),
+        (LRGS_CONF.to_string(), ByteString(config_file_data)),
+    ]);
+    let file_hashes: BTreeMap<String, String> = data
+        .iter()
+        .map(|(file, contents)| {
+            let hash = Sha256::digest(&contents.0);
+            (file.clone(), base16ct::lower::encode_string(&hash))
+        })
+        .collect();
+    let mut hasher = Sha256::new();
+    for (file, hash) in &file_hashes {
+        hasher.update(file.as_bytes());
+        hasher.update(hash.as_bytes());
+    }
+
     let secret = Secret {
-        data: Some(BTreeMap::from([
-            (".lrgs.passwd".to_string(), ByteString(password_file_data)),
-            ("ddsrecv.conf".to_string(), ByteString(dds_config_data)),
-            ("drgsconf.xml".to_string(), ByteString(drgs_config_data)),
-            ("lrgs.conf".to_string(), ByteString(config_file_data)),
-        ])),
+        data: Some(data),
         metadata: ObjectMeta {
             name: Some(format!("{}-lrgs-configuration", &owner_ref.name)),
             namespace: Some(namespace.clone()),
@@ -235,63 +249,180 @@ noTimeout: true
 
     let hash = base16ct::lower::encode_string(&hasher.finalize());
     debug!("Calculated hash is: {hash}");
-    return Ok(LrgsConfig { secret, hash });
+    Ok(LrgsConfig {
+        secret,
+        hash,
+        file_hashes,
+    })
 }
 
-pub async fn create_managed_users(
-    client: Client,
+/// Managed DDS user, and its Secret, for applications reading from the cluster.
+pub const ROUTING_USER: &str = "routing-user";
+
+/// Users the cluster itself relies on.
+pub const MANAGED_USERS: [&str; 3] = ["lrgsadmin", "replication", ROUTING_USER];
+
+/// Secrets for the users the cluster itself relies on. Only users for which `exists` is false
+/// are returned unless `rotate` is set, in which case every user gets a new password.
+pub fn create_managed_users(
     lrgs_cluster: &LrgsCluster,
     owner_ref: &OwnerReference,
-) -> Result<Vec<Secret>> {
-    let ns = lrgs_cluster.metadata.namespace.clone().unwrap();
-    let cluster_name = lrgs_cluster.metadata.name.clone().unwrap();
-    let secrets_api: Api<Secret> = Api::namespaced(client, &ns);
-    let required = Vec::from(["lrgsadmin", "replication", "routing-user"]);
+    rotate: bool,
+    exists: impl Fn(&str) -> bool,
+) -> Vec<Secret> {
     let mut managed_users = Vec::new();
-    for user in required {
-        match secrets_api.get_opt(user).await? {
-            Some(_) => debug!("User already exists."), // Perhaps we should put a rotation here
-            None => {
-                let password = PasswordGenerator {
-                    length: 64,
-                    numbers: true,
-                    lowercase_letters: true,
-                    uppercase_letters: true,
-                    symbols: false,
-                    spaces: false,
-                    exclude_similar_characters: false,
-                    strict: true,
-                }
-                .generate_one()
-                .unwrap();
-                let roles = match user {
-                    "lrgsadmin" => "dds,lrgsadmin",
-                    "replication" => "dds",
-                    "routing-user" => "dds",
-                    &_ => "",
-                };
-                managed_users.push(Secret {
-                    data: Some(BTreeMap::from([
-                        ("username".to_string(), ByteString(Vec::from(user))),
-                        ("password".to_string(), ByteString(Vec::from(password))),
-                        ("roles".to_string(), ByteString(Vec::from(roles))),
-                    ])),
-                    type_: Some("lrgs.opendcs.org/ddsuser".to_string()),
-                    metadata: ObjectMeta {
-                        name: Some(user.to_string()),
-                        namespace: lrgs_cluster.metadata.namespace.clone(),
-                        owner_references: Some(vec![owner_ref.clone()]),
-                        annotations: Some(BTreeMap::from([(
-                            format!("{}/for-cluster", LRGS_GROUP.as_str()).clone(),
-                            cluster_name.clone(),
-                        )])),
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                });
+    for user in MANAGED_USERS {
+        if !rotate && exists(user) {
+            debug!("User already exists.");
+            continue;
+        }
+        managed_users.push(managed_user_secret(
+            lrgs_cluster,
+            owner_ref,
+            user,
+            &generate_password(),
+        ));
+    }
+
+    managed_users
+}
+
+/// Annotation requesting new passwords, on an LrgsCluster for its managed users or on a DDS
+/// user Secret for that user.
+pub fn rotate_credentials_annotation() -> String {
+    format!("{}/rotate-credentials", LRGS_GROUP.as_str())
+}
+
+/// Annotation on a DDS user Secret holding the rotate-credentials value last acted on.
+pub fn last_rotation_request_annotation() -> String {
+    format!("{}/last-rotation-request", LRGS_GROUP.as_str())
+}
+
+/// DDS users whose rotate-credentials annotation holds a value not acted on yet, with a new
+/// password and that value recorded.
+pub fn users_to_rotate(users: &[Secret]) -> Vec<Secret> {
+    let last_request = last_rotation_request_annotation();
+    users
+        .iter()
+        .filter_map(|user| {
+            let requested = user.annotations().get(&rotate_credentials_annotation())?;
+            if !rotation_due(
+                None,
+                None,
+                Some(requested),
+                user.annotations().get(&last_request),
+            ) {
+                return None;
             }
-        };
+            let mut user = user.clone();
+            user.annotations_mut()
+                .insert(last_request.clone(), requested.clone());
+            if let Some(string_data) = user.string_data.as_mut() {
+                string_data.remove("password");
+            }
+            user.data.get_or_insert_with(BTreeMap::new).insert(
+                "password".to_string(),
+                ByteString(generate_password().into_bytes()),
+            );
+            Some(user)
+        })
+        .collect()
+}
+
+pub fn managed_user_secret(
+    lrgs_cluster: &LrgsCluster,
+    owner_ref: &OwnerReference,
+    user: &str,
+    password: &str,
+) -> Secret {
+    let cluster_name = lrgs_cluster.metadata.name.clone().unwrap();
+    let roles = match user {
+        "lrgsadmin" => "dds,lrgsadmin",
+        "replication" => "dds",
+        ROUTING_USER => "dds",
+        &_ => "",
+    };
+    Secret {
+        data: Some(BTreeMap::from([
+            ("username".to_string(), ByteString(Vec::from(user))),
+            ("password".to_string(), ByteString(Vec::from(password))),
+            ("roles".to_string(), ByteString(Vec::from(roles))),
+        ])),
+        type_: Some(DDS_USER_TYPE.to_string()),
+        metadata: ObjectMeta {
+            name: Some(user.to_string()),
+            namespace: lrgs_cluster.metadata.namespace.clone(),
+            owner_references: Some(vec![owner_ref.clone()]),
+            annotations: Some(BTreeMap::from([(
+                format!("{}/for-cluster", LRGS_GROUP.as_str()).clone(),
+                cluster_name,
+            )])),
+            ..Default::default()
+        },
+        ..Default::default()
     }
+}
 
-    Ok(managed_users)
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::v1::{dds_recv::DdsConnectionSpec, lrgs::LrgsClusterSpec};
+    use kube::Resource;
+
+    #[test]
+    fn receiver_changes_do_not_restart_lrgs() {
+        let mut cluster = LrgsCluster::new(
+            "main",
+            LrgsClusterSpec {
+                replicas: 1,
+                storage_class: "standard".to_string(),
+                storage_size: "1Gi".to_string(),
+                archive_length_days: None,
+                credential_rotation: None,
+            },
+        );
+        cluster.metadata.namespace = Some("lrgs".to_string());
+        cluster.metadata.uid = Some("uid".to_string());
+        let owner_ref = cluster.controller_owner_ref(&()).unwrap();
+        let mut inputs = LrgsInputs::default();
+        let before = render_lrgs_config(&cluster, &owner_ref, &inputs).unwrap();
+        inputs.dds_connections.push(DdsConnection::new(
+            "upstream",
+            DdsConnectionSpec {
+                hostname: "upstream.test".to_string(),
+                port: 16003,
+                enabled: Some(true),
+                username: "user".to_string(),
+                tls_mode: None,
+            },
+        ));
+        let after = render_lrgs_config(&cluster, &owner_ref, &inputs).unwrap();
+        assert_ne!(before.hash, after.hash);
+        assert_ne!(
+            before.file_hashes["ddsrecv.conf"],
+            after.file_hashes["ddsrecv.conf"]
+        );
+        assert_eq!(before.restart_hash(), after.restart_hash());
+
+        cluster.spec.archive_length_days = Some(62);
+        let resized = render_lrgs_config(&cluster, &owner_ref, &inputs).unwrap();
+        assert_ne!(after.restart_hash(), resized.restart_hash());
+    }
+
+    #[test]
+    fn user_secrets_need_username_and_password() {
+        let user = Secret {
+            metadata: ObjectMeta {
+                name: Some("guest".to_string()),
+                ..Default::default()
+            },
+            data: Some(BTreeMap::from([(
+                "username".to_string(),
+                ByteString(b"guest".to_vec()),
+            )])),
+            ..Default::default()
+        };
+        let error = render_password_file(&[user]).unwrap_err();
+        assert_eq!(error.to_string(), "Secret guest has no password");
+    }
 }
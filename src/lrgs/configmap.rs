@@ -30,3 +30,72 @@ pub fn created_script_config_map(
         hash,
     )
 }
+
+/// ConfigMap mounted at /netlists in the pods of a cluster.
+pub fn netlists_config_map_name(cluster: &str) -> String {
+    format!("{cluster}-lrgs-netlists")
+}
+
+/// The exported netlists of a cluster in one ConfigMap. The pods always mount this one, so
+/// netlists come and go through the volume and lrgs.sh instead of a new pod template.
+pub fn netlists_config_map(
+    namespace: String,
+    owner_ref: &OwnerReference,
+    netlists: &[ConfigMap],
+) -> ConfigMap {
+    let data = netlists
+        .iter()
+        .filter_map(|netlist| netlist.data.clone())
+        .flatten()
+        .collect();
+    ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(netlists_config_map_name(&owner_ref.name)),
+            namespace: Some(namespace),
+            owner_references: Some(vec![owner_ref.clone()]),
+            labels: Some(BTreeMap::from([(
+                "lrgs.opendcs.org/for-cluster".to_string(),
+                owner_ref.name.clone(),
+            )])),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn netlist(file: &str) -> ConfigMap {
+        ConfigMap {
+            data: Some(BTreeMap::from([(
+                file.to_string(),
+                "12345678:SITE\n".to_string(),
+            )])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn netlists_are_collected_in_one_config_map() {
+        let owner_ref = OwnerReference {
+            name: "main".to_string(),
+            ..Default::default()
+        };
+        let config_map = netlists_config_map(
+            "lrgs".to_string(),
+            &owner_ref,
+            &[netlist("east.nl"), ConfigMap::default(), netlist("west.nl")],
+        );
+        assert_eq!(
+            config_map.metadata.name.as_deref(),
+            Some("main-lrgs-netlists")
+        );
+        assert_eq!(
+            config_map.data.unwrap().keys().collect::<Vec<_>>(),
+            vec!["east.nl", "west.nl"]
+        );
+    }
+}
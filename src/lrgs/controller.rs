@@ -1,16 +1,26 @@
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
 use crate::{
-    api::v1::{
-        dds_recv::DdsConnection,
-        lrgs::{LrgsCluster, LrgsClusterStatus},
+    api::{
+        constants::LRGS_GROUP,
+        v1::{
+            lrgs::{LrgsCluster, LrgsClusterStatus},
+            netlist::Netlist,
+        },
     },
+    apps::netlist::netlist_config_map_name,
+    credentials::rotation_due,
     lrgs::{
-        config::{create_lrgs_config, create_managed_users},
-        configmap::created_script_config_map,
+        cache::{LrgsCaches, clusters_in_namespace, reflectors},
+        config::{
+            create_managed_users, last_rotation_request_annotation, render_lrgs_config,
+            users_to_rotate,
+        },
+        configmap::{created_script_config_map, netlists_config_map},
         service::create_service,
         statefulset::create_statefulset,
     },
+    settings::scoped_api,
     telemetry::{
         state::{Context, State},
         telemetry,
@@ -31,59 +41,77 @@ use kube::{
 use serde_json::json;
 use tracing::{Span, error, field, info, instrument, warn};
 
-pub async fn run(state: State<LrgsCluster>) {
-    let client = Client::try_default()
-        .await
-        .expect("failed to create kube Client");
-
-    let secrets: Api<Secret> = Api::all(client.clone());
-    let cm: Api<ConfigMap> = Api::all(client.clone());
-    let services: Api<Service> = Api::all(client.clone());
-    let lrgs_cluster: Api<LrgsCluster> = Api::all(client.clone());
-    let dds_connections: Api<DdsConnection> = Api::all(client.clone());
-    let stateful_set: Api<StatefulSet> = Api::all(client.clone());
-    let user_watch_config =
-        watcher::Config::default().fields("type=LrgsCluster.opendcs.org/ddsuser");
+pub async fn run(state: State<LrgsCluster>, client: Client) {
+    let settings = state.settings.clone();
+    settings
+        .for_each_namespace(|namespace| run_in(state.clone(), client.clone(), namespace))
+        .await;
+}
 
-    let user_mapper = |obj: Secret| {
-        let binding = obj.metadata.labels.unwrap();
-        let name = binding.get("lrgs.opendcs.org/lrgs-cluster").unwrap();
-        let namespace = obj.metadata.namespace.unwrap_or("default".to_string());
-        let obj_ref = ObjectRef::new(name).within(&namespace);
-        Some(obj_ref)
-    };
+async fn run_in(state: State<LrgsCluster>, client: Client, namespace: Option<String>) {
+    let namespace = namespace.as_deref();
+    let secrets: Api<Secret> = scoped_api(client.clone(), namespace);
+    let cm: Api<ConfigMap> = scoped_api(client.clone(), namespace);
+    let services: Api<Service> = scoped_api(client.clone(), namespace);
+    let lrgs_cluster: Api<LrgsCluster> = scoped_api(client.clone(), namespace);
+    let stateful_set: Api<StatefulSet> = scoped_api(client.clone(), namespace);
+    let (caches, subscribers, reflectors) = reflectors(client.clone(), namespace);
 
-    let dds_mapper = |obj: DdsConnection| {
-        let binding = obj.metadata.labels.unwrap();
-        let name = binding.get("lrgs.opendcs.org/lrgs-cluster").unwrap();
-        let namespace = obj.metadata.namespace.unwrap_or("default".to_string());
-        let obj_ref = ObjectRef::new(name).within(&namespace);
-        Some(obj_ref)
+    let controller = Controller::new(lrgs_cluster, watcher::Config::default())
+        .with_config(state.settings.controller_config());
+    let (dds_clusters, drgs_clusters, user_clusters) =
+        (controller.store(), controller.store(), controller.store());
+    let netlist_mapper = |obj: Arc<Netlist>| {
+        let namespace = obj.namespace().unwrap_or("default".to_string());
+        Some(ObjectRef::new(&obj.spec.lrgs_cluster).within(&namespace))
     };
-
-    println!("Starting controller");
-    Controller::new(lrgs_cluster.clone(), watcher::Config::default())
+    let controller = controller
         .owns(stateful_set, watcher::Config::default())
-        .owns(secrets.clone(), watcher::Config::default())
-        .owns(services.clone(), watcher::Config::default())
+        .owns(secrets, watcher::Config::default())
+        .owns(services, watcher::Config::default())
         .owns(cm, watcher::Config::default())
-        .watches(secrets.clone(), user_watch_config, user_mapper)
-        .watches(
-            dds_connections.clone(),
-            watcher::Config::default(),
-            dds_mapper,
-        )
-        .shutdown_on_signal()
-        .run(reconcile, error_policy, state.to_context(client).await)
-        .filter_map(|x| async move { std::result::Result::ok(x) })
-        .for_each(|_| futures::future::ready(()))
-        .await;
+        .watches_shared_stream(subscribers.dds_connections, move |c| {
+            clusters_in_namespace(&dds_clusters, &*c)
+        })
+        .watches_shared_stream(subscribers.drgs_connections, move |c| {
+            clusters_in_namespace(&drgs_clusters, &*c)
+        })
+        .watches_shared_stream(subscribers.users, move |u| {
+            clusters_in_namespace(&user_clusters, &*u)
+        })
+        .watches_shared_stream(subscribers.netlists, netlist_mapper)
+        .shutdown_on_signal();
+    let context = state.to_context(client).await;
+
+    println!("Starting controller");
+    let controller = async move {
+        // Configuration rendered from partially filled caches would drop connections and users.
+        if let Err(e) = caches.wait_until_ready().await {
+            error!("Caches for the lrgs controller did not sync: {:?}", e);
+            return;
+        }
+        controller
+            .run(
+                move |obj, ctx| reconcile(obj, ctx, caches.clone()),
+                error_policy,
+                context,
+            )
+            .filter_map(|x| async move { std::result::Result::ok(x) })
+            .for_each(|_| futures::future::ready(()))
+            .await;
+    };
+    // The reflectors never finish on their own, stop with the controller.
+    tokio::select! {
+        _ = controller => {},
+        _ = reflectors => {},
+    }
 }
 
-#[instrument(skip(object, ctx), fields(trace_id))]
+#[instrument(skip(object, ctx, caches), fields(trace_id))]
 async fn reconcile(
     object: Arc<LrgsCluster>,
     ctx: Arc<Context<LrgsCluster>>,
+    caches: LrgsCaches,
 ) -> Result<Action, Error> {
     let trace_id = telemetry::get_trace_id();
     if trace_id != opentelemetry::trace::TraceId::INVALID {
@@ -106,30 +134,103 @@ async fn reconcile(
     let secrets_api: Api<Secret> = Api::namespaced(client.clone(), &ns);
     let service_api: Api<Service> = Api::namespaced(client.clone(), &ns);
 
+    let patch_name = "lrgs-controller";
+    let serverside = PatchParams::apply(patch_name);
+
+    let status = object.status.clone();
+    let rotation_request = object
+        .annotations()
+        .get(&format!("{}/rotate-credentials", LRGS_GROUP.as_str()))
+        .cloned();
+    let rotate = rotation_due(
+        object.spec.credential_rotation.as_ref(),
+        status.as_ref().and_then(|s| s.credentials_rotated),
+        rotation_request.as_ref(),
+        status
+            .as_ref()
+            .and_then(|s| s.last_rotation_request.as_ref()),
+    );
+    if rotate {
+        info!("Rotating managed user credentials for {}/{}", ns, name);
+    }
+    // Users are applied ahead of the configuration so new passwords land in the
+    // password file in this pass. lrgs.sh picks the new file up without a restart.
+    let lrgs_managed_users = create_managed_users(&object, &oref, rotate, |user| {
+        caches.user(&ns, user).is_some()
+    });
+    let mut inputs = caches.inputs(&ns);
+    for user in lrgs_managed_users {
+        // The cache has not seen the applied users yet, render with what was applied.
+        inputs.users.retain(|u| u.name_any() != user.name_any());
+        inputs.users.push(user.clone());
+        secrets_api
+            .patch(&user.name_any(), &serverside, &Patch::Apply(user))
+            .await?;
+    }
+
+    for user in users_to_rotate(&inputs.users) {
+        info!(
+            "Rotating the password of DDS user {}/{}",
+            ns,
+            user.name_any()
+        );
+        let last_request = last_rotation_request_annotation();
+        // The resource version fails the patch if another reconcile rotated the user first.
+        let patch = json!({
+            "metadata": {
+                "resourceVersion": user.resource_version(),
+                "annotations": {(last_request.clone()): user.annotations().get(&last_request)},
+            },
+            "data": {"password": user.data.as_ref().and_then(|d| d.get("password"))},
+        });
+        match secrets_api
+            .patch(
+                &user.name_any(),
+                &PatchParams::default(),
+                &Patch::Merge(patch),
+            )
+            .await
+        {
+            Ok(_) => {
+                inputs.users.retain(|u| u.name_any() != user.name_any());
+                inputs.users.push(user);
+            }
+            Err(Error::Api(e)) if e.code == 409 => {
+                info!(
+                    "DDS user {}/{} changed, rotating on the next pass",
+                    ns,
+                    user.name_any()
+                )
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
     let (lrgs_config_map, script_hash) = created_script_config_map(ns.clone(), &oref);
-    let lrgs_config = create_lrgs_config(client.clone(), &object, &oref).await;
+    let lrgs_config = render_lrgs_config(&object, &oref, &inputs);
     if lrgs_config.is_err() {
         let error = lrgs_config.err().unwrap();
         error!("Unable to build Configuration for Lrgs Cluster {}", error);
         //return Err(kube::Error::ReadEvents(io::Error::new(ErrorKind::Other, error.to_string())))  ;
-        return Ok(Action::requeue(Duration::from_secs(3600)));
+        return Ok(Action::requeue(ctx.settings.error_backoff));
     }
     let lrgs_config = lrgs_config.ok().unwrap();
-    let lrgs_config_secret = lrgs_config.secret;
+    let lrgs_config_secret = lrgs_config.secret.clone();
 
-    let lrgs_managed_users = match create_managed_users(client.clone(), &object, &oref).await {
-        Ok(lmu) => lmu,
-        Err(e) => {
-            println!("Unable to process managed users. {:?}", e);
-            Vec::new()
+    let lrgs_service = create_service(&object, &oref);
+    // Netlists without a ConfigMap have not been exported yet.
+    let mut netlists = Vec::new();
+    for netlist in caches.netlists_for(&object) {
+        if let Some(config_map) = config_map_api
+            .get_opt(&netlist_config_map_name(&netlist))
+            .await?
+        {
+            netlists.push(config_map);
         }
-    };
-
-    let lrgs_service = create_service(client.clone(), &object, &oref);
+    }
+    let netlists_config_map = netlists_config_map(ns.clone(), &oref, &netlists);
     let lrgs_statefulset =
-        create_statefulset(&object, lrgs_config.hash.clone(), script_hash.clone());
-    let patch_name = "lrgs-controller";
-    let serverside = PatchParams::apply(patch_name);
+        create_statefulset(&object, lrgs_config.restart_hash(), script_hash.clone());
     secrets_api
         .patch(
             &lrgs_config_secret.name_any(),
@@ -144,6 +245,13 @@ async fn reconcile(
             &Patch::Apply(lrgs_config_map),
         )
         .await?;
+    config_map_api
+        .patch(
+            &netlists_config_map.name_any(),
+            &serverside,
+            &Patch::Apply(netlists_config_map),
+        )
+        .await?;
     stateful_api
         .patch(
             &lrgs_statefulset.name_any(),
@@ -157,16 +265,15 @@ async fn reconcile(
             .await?;
     }
 
-    for user in lrgs_managed_users {
-        secrets_api
-            .patch(&user.name_any(), &serverside, &Patch::Apply(user))
-            .await?;
-    }
-
-    if object
-        .status
-        .as_ref()
-        .is_none_or(|lrgs| lrgs.checksum != lrgs_config.hash)
+    // Clusters created before rotation was tracked get their baseline recorded here.
+    let credentials_rotated = match status.as_ref().and_then(|s| s.credentials_rotated) {
+        Some(rotated) if !rotate => Some(rotated),
+        _ => Some(Utc::now()),
+    };
+    if rotate
+        || status.as_ref().is_none_or(|lrgs| {
+            lrgs.checksum != lrgs_config.hash || lrgs.credentials_rotated.is_none()
+        })
     {
         // always overwrite status object with what we saw
         let new_status = Patch::Apply(json!({
@@ -174,13 +281,18 @@ async fn reconcile(
             "kind": "LrgsCluster",
             "status": LrgsClusterStatus {
                 checksum: lrgs_config.hash.clone(),
-                last_updated: Some(Utc::now()) }
+                file_hashes: lrgs_config.file_hashes.clone(),
+                last_updated: Some(Utc::now()),
+                credentials_rotated,
+                last_rotation_request: rotation_request
+                    .or(status.and_then(|s| s.last_rotation_request)),
+            }
         }));
-        let ps = PatchParams::apply(&patch_name).force();
+        let ps = PatchParams::apply(patch_name).force();
         let _o = lrgs_api.patch_status(&name, &ps, &new_status).await?;
     }
 
-    Ok(Action::requeue(Duration::from_secs(3600 / 2)))
+    Ok(Action::requeue(ctx.settings.requeue))
 }
 
 fn error_policy(
@@ -191,5 +303,5 @@ fn error_policy(
     warn!("reconcile failed: {:?}", err);
     let e = anyhow!("Api error {:?}", err);
     ctx.metrics.reconcile.set_failure(&object, &e);
-    Action::requeue(Duration::from_secs(5 * 60))
+    Action::requeue(ctx.settings.error_backoff)
 }
@@ -0,0 +1,468 @@
+//! Import of the configuration files of an existing LRGS installation into the resources the
+//! operator manages.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Result, bail};
+use k8s_openapi::api::core::v1::Secret;
+use kube::api::ObjectMeta;
+use serde_json::Value;
+
+use crate::{
+    api::v1::{
+        dds_recv::{DdsConnection, DdsConnectionSpec, TlsMode},
+        drgs::{DrgsConnection, DrgsConnectionSpec},
+        lrgs::{LrgsCluster, LrgsClusterSpec},
+    },
+    credentials::generate_password,
+    lrgs::{
+        config::{DDS_USER_TYPE, rotate_credentials_annotation},
+        password_file::{PasswordEntry, parse_password_file},
+        xml::{self, Element},
+    },
+};
+
+/// lrgs.conf settings the operator writes itself.
+const MANAGED_SETTINGS: [&str; 10] = [
+    "archiveDir",
+    "enableDdsRecv",
+    "ddsRecvConfig",
+    "enableDrgsRecv",
+    "drgsRecvConfig",
+    "numDayFiles",
+    "htmlStatusSeconds",
+    "ddsListenPort",
+    "ddsRequireAuth",
+    "noTimeout",
+];
+
+/// Contents of the legacy files, any of which may be missing.
+#[derive(Clone, Debug, Default)]
+pub struct LegacyFiles {
+    pub ddsrecv_conf: Option<String>,
+    pub drgsconf_xml: Option<String>,
+    pub lrgs_conf: Option<String>,
+    pub lrgs_passwd: Option<String>,
+}
+
+/// LrgsCluster settings that have no equivalent in the legacy files.
+#[derive(Clone, Debug)]
+pub struct ClusterSettings {
+    pub name: String,
+    pub namespace: Option<String>,
+    pub replicas: i32,
+    pub storage_class: String,
+    pub storage_size: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct LegacyImport {
+    pub lrgs_cluster: LrgsCluster,
+    pub dds_connections: Vec<DdsConnection>,
+    pub drgs_connections: Vec<DrgsConnection>,
+    /// DDS user Secrets, with new passwords and marked for rotation.
+    pub users: Vec<Secret>,
+    /// Settings that were skipped or could not be carried over.
+    pub warnings: Vec<String>,
+}
+
+impl LegacyImport {
+    /// Every resource, ready for [`crate::render::to_yaml`].
+    pub fn objects(&self) -> Result<Vec<Value>> {
+        let mut objects = vec![serde_json::to_value(&self.lrgs_cluster)?];
+        for connection in &self.dds_connections {
+            objects.push(serde_json::to_value(connection)?);
+        }
+        for connection in &self.drgs_connections {
+            objects.push(serde_json::to_value(connection)?);
+        }
+        for user in &self.users {
+            objects.push(serde_json::to_value(user)?);
+        }
+        Ok(objects)
+    }
+}
+
+/// Lowercase RFC 1123 name for a resource, falling back when nothing usable is left.
+pub fn resource_name(name: &str, fallback: &str) -> String {
+    let name: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let name = name.trim_matches('-');
+    let name = name[..name.len().min(63)].trim_end_matches('-');
+    if name.is_empty() {
+        fallback.to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Make names unique by appending the connection, or entry, number where needed.
+fn unique_name(names: &mut Vec<String>, name: String, number: &str) -> String {
+    let mut name = name;
+    // A suffixed name can itself be taken, `a-2` before `a` and `A` for instance.
+    while names.contains(&name) {
+        name = format!("{name}-{number}");
+    }
+    names.push(name.clone());
+    name
+}
+
+fn metadata(name: String, namespace: &Option<String>) -> ObjectMeta {
+    ObjectMeta {
+        name: Some(name),
+        namespace: namespace.clone(),
+        ..Default::default()
+    }
+}
+
+fn root(document: &str, expected: &str) -> Result<Element> {
+    let root = xml::parse(document)?;
+    if root.name != expected {
+        bail!("expected <{expected}>, found <{}>", root.name);
+    }
+    Ok(root)
+}
+
+fn parse_bool(text: Option<&str>) -> Option<bool> {
+    text.map(|t| t.eq_ignore_ascii_case("true"))
+}
+
+/// DdsConnections from a ddsrecv.conf.
+pub fn parse_ddsrecv_conf(
+    document: &str,
+    namespace: &Option<String>,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<DdsConnection>> {
+    let root = root(document, "ddsrecvconf")?;
+    let mut names = Vec::new();
+    let mut connections = Vec::new();
+    for connection in root.children("connection") {
+        let number = connection.attribute("number").unwrap_or_default();
+        let Some(hostname) = connection.attribute("host").filter(|h| !h.is_empty()) else {
+            warnings.push(format!(
+                "ddsrecv.conf connection {number} has no host, skipped"
+            ));
+            continue;
+        };
+        let Some(username) = connection.child_text("username") else {
+            warnings.push(format!(
+                "ddsrecv.conf connection {number} ({hostname}) has no username, skipped"
+            ));
+            continue;
+        };
+        let tls_mode = match connection.child_text("use-tls") {
+            None | Some("NONE") => None,
+            Some("START_TLS") => Some(TlsMode::StartTls),
+            Some("TLS") => Some(TlsMode::Tls),
+            Some(other) => {
+                warnings.push(format!(
+                    "ddsrecv.conf connection {number} has unknown use-tls {other}, TLS not set"
+                ));
+                None
+            }
+        };
+        let port = match connection.child_text("port").map(str::parse) {
+            Some(Ok(port)) => port,
+            Some(Err(_)) | None => 16003,
+        };
+        let name = connection
+            .child_text("name")
+            .unwrap_or(hostname)
+            .to_string();
+        let name = unique_name(
+            &mut names,
+            resource_name(&name, &format!("dds-{number}")),
+            number,
+        );
+        connections.push(DdsConnection {
+            metadata: metadata(name, namespace),
+            spec: DdsConnectionSpec {
+                hostname: hostname.to_string(),
+                port,
+                enabled: parse_bool(connection.child_text("enabled")),
+                username: username.to_string(),
+                tls_mode,
+            },
+        });
+    }
+    Ok(connections)
+}
+
+/// DrgsConnections from a drgsconf.xml.
+pub fn parse_drgsconf(
+    document: &str,
+    namespace: &Option<String>,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<DrgsConnection>> {
+    let root = root(document, "drgsconf")?;
+    let mut names = Vec::new();
+    let mut connections = Vec::new();
+    for connection in root.children("connection") {
+        let number = connection.attribute("number").unwrap_or_default();
+        let Some(hostname) = connection.attribute("host").filter(|h| !h.is_empty()) else {
+            warnings.push(format!(
+                "drgsconf.xml connection {number} has no host, skipped"
+            ));
+            continue;
+        };
+        let Some(start_pattern) = connection.child_text("startpattern") else {
+            warnings.push(format!(
+                "drgsconf.xml connection {number} ({hostname}) has no startpattern, skipped"
+            ));
+            continue;
+        };
+        let port = |element: &str, default: u16| {
+            connection
+                .child_text(element)
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(default)
+        };
+        let name = connection
+            .child_text("name")
+            .unwrap_or(hostname)
+            .to_string();
+        let name = unique_name(
+            &mut names,
+            resource_name(&name, &format!("drgs-{number}")),
+            number,
+        );
+        connections.push(DrgsConnection {
+            metadata: metadata(name, namespace),
+            spec: DrgsConnectionSpec {
+                hostname: hostname.to_string(),
+                event_port: port("evtport", 17011),
+                message_port: port("msgport", 17010),
+                enabled: parse_bool(connection.child_text("enabled")),
+                event_enabled: parse_bool(connection.child_text("evtenabled")),
+                start_pattern: start_pattern.to_string(),
+            },
+        });
+    }
+    Ok(connections)
+}
+
+/// Settings of an lrgs.conf, `name: value` or `name=value` per line.
+pub fn parse_lrgs_conf(contents: &str) -> BTreeMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with('!'))
+        .filter_map(|line| {
+            let split = line.find([':', '='])?;
+            Some((
+                line[..split].trim().to_string(),
+                line[split + 1..].trim().to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// DDS user Secret for a password file entry. The hash cannot be reversed, so the user gets
+/// a new password. The rotate-credentials annotation has the lrgs controller replace it once
+/// applied, the password printed with the manifests is never used.
+pub fn user_secret(entry: &PasswordEntry, name: String, namespace: &Option<String>) -> Secret {
+    Secret {
+        type_: Some(DDS_USER_TYPE.to_string()),
+        metadata: ObjectMeta {
+            annotations: Some(BTreeMap::from([(
+                rotate_credentials_annotation(),
+                "imported".to_string(),
+            )])),
+            ..metadata(name, namespace)
+        },
+        string_data: Some(BTreeMap::from([
+            ("username".to_string(), entry.username.clone()),
+            ("password".to_string(), generate_password()),
+            ("roles".to_string(), entry.roles.join(",")),
+        ])),
+        ..Default::default()
+    }
+}
+
+/// Resources for an existing installation.
+pub fn import(files: &LegacyFiles, settings: &ClusterSettings) -> Result<LegacyImport> {
+    let namespace = &settings.namespace;
+    let mut warnings = Vec::new();
+    let dds_connections = match &files.ddsrecv_conf {
+        Some(document) => parse_ddsrecv_conf(document, namespace, &mut warnings)?,
+        None => vec![],
+    };
+    let drgs_connections = match &files.drgsconf_xml {
+        Some(document) => parse_drgsconf(document, namespace, &mut warnings)?,
+        None => vec![],
+    };
+    let mut user_names = Vec::new();
+    let users = files
+        .lrgs_passwd
+        .as_deref()
+        .map(parse_password_file)
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+        .map(|(number, entry)| {
+            // Usernames differing only in case or punctuation share a resource name.
+            let name = unique_name(
+                &mut user_names,
+                resource_name(&entry.username, "dds-user"),
+                &number.to_string(),
+            );
+            user_secret(entry, name, namespace)
+        })
+        .collect();
+
+    let lrgs_conf = files
+        .lrgs_conf
+        .as_deref()
+        .map(parse_lrgs_conf)
+        .unwrap_or_default();
+    let archive_length_days = match lrgs_conf.get("numDayFiles").map(|d| d.parse()) {
+        Some(Ok(days)) => Some(days),
+        Some(Err(_)) => {
+            warnings.push("lrgs.conf numDayFiles is not a number, default used".to_string());
+            None
+        }
+        None => None,
+    };
+    for setting in lrgs_conf.keys() {
+        if !MANAGED_SETTINGS.contains(&setting.as_str()) {
+            warnings.push(format!("lrgs.conf setting {setting} is not carried over"));
+        }
+    }
+    let mut lrgs_cluster = LrgsCluster::new(
+        &settings.name,
+        LrgsClusterSpec {
+            replicas: settings.replicas,
+            storage_class: settings.storage_class.clone(),
+            storage_size: settings.storage_size.clone(),
+            archive_length_days,
+            credential_rotation: None,
+        },
+    );
+    lrgs_cluster.metadata.namespace = namespace.clone();
+
+    Ok(LegacyImport {
+        lrgs_cluster,
+        dds_connections,
+        drgs_connections,
+        users,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lrgs::config::users_to_rotate;
+    use kube::ResourceExt;
+
+    #[test]
+    fn legacy_files_import_as_resources() {
+        let files = LegacyFiles {
+            ddsrecv_conf: Some(
+                r#"<?xml version="1.0"?>
+<ddsrecvconf>
+  <!-- primary feed -->
+  <connection number="0" host="cdadata.wcda.noaa.gov">
+    <enabled>true</enabled>
+    <port>16003</port>
+    <name>CDADATA</name>
+    <username>usgs&amp;co</username>
+    <use-tls>TLS</use-tls>
+  </connection>
+  <connection number="1" host="backup.test">
+    <name>CDADATA</name>
+  </connection>
+</ddsrecvconf>"#
+                    .to_string(),
+            ),
+            drgsconf_xml: Some(
+                r#"<drgsconf><debug>3</debug>
+  <connection number="0" host="east-drgs.test">
+    <name>DRGS-E</name>
+    <msgport>17020</msgport>
+    <evtenabled>true</evtenabled>
+    <startpattern>534D0D0A</startpattern>
+  </connection>
+</drgsconf>"#
+                    .to_string(),
+            ),
+            lrgs_conf: Some("# archive\nnumDayFiles: 62\nmergePref1=cdadata\n".to_string()),
+            lrgs_passwd: Some(
+                "lrgsadmin:dds,lrgsadmin:ABC123:\nguest:none:DEF456:\nGuest:none:GHI789:\n"
+                    .to_string(),
+            ),
+        };
+        let settings = ClusterSettings {
+            name: "legacy".to_string(),
+            namespace: None,
+            replicas: 1,
+            storage_class: "standard".to_string(),
+            storage_size: "30Gi".to_string(),
+        };
+        let imported = import(&files, &settings).unwrap();
+
+        assert_eq!(imported.lrgs_cluster.spec.archive_length_days, Some(62));
+        assert_eq!(imported.dds_connections.len(), 1);
+        let dds = &imported.dds_connections[0];
+        assert_eq!(dds.metadata.name.as_deref(), Some("cdadata"));
+        assert_eq!(dds.spec.username, "usgs&co");
+        assert!(matches!(dds.spec.tls_mode, Some(TlsMode::Tls)));
+        let drgs = &imported.drgs_connections[0];
+        assert_eq!(drgs.metadata.name.as_deref(), Some("drgs-e"));
+        assert_eq!(drgs.spec.message_port, 17020);
+        assert_eq!(drgs.spec.event_port, 17011);
+        let roles: Vec<String> = imported
+            .users
+            .iter()
+            .map(|u| u.string_data.as_ref().unwrap()["roles"].clone())
+            .collect();
+        assert_eq!(roles, vec!["dds,lrgsadmin", "", ""]);
+        let names: Vec<&str> = imported
+            .users
+            .iter()
+            .map(|u| u.metadata.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, vec!["lrgsadmin", "guest", "guest-2"]);
+        // Imported passwords are replaced by the controller, once.
+        let rotated = users_to_rotate(&imported.users);
+        assert_eq!(rotated.len(), 3);
+        for (user, imported) in rotated.iter().zip(&imported.users) {
+            assert_eq!(
+                imported.annotations()[&rotate_credentials_annotation()],
+                "imported"
+            );
+            assert_ne!(
+                user.data.as_ref().unwrap()["password"].0,
+                imported.string_data.as_ref().unwrap()["password"].as_bytes()
+            );
+            assert!(!user.string_data.as_ref().unwrap().contains_key("password"));
+        }
+        assert!(users_to_rotate(&rotated).is_empty());
+        assert_eq!(
+            imported.warnings,
+            vec![
+                "ddsrecv.conf connection 1 (backup.test) has no username, skipped",
+                "lrgs.conf setting mergePref1 is not carried over",
+            ]
+        );
+    }
+    #[test]
+    fn suffixed_names_stay_unique() {
+        let mut names = Vec::new();
+        let unique: Vec<String> = ["a-2", "a", "A"]
+            .iter()
+            .enumerate()
+            .map(|(number, name)| {
+                unique_name(
+                    &mut names,
+                    resource_name(name, "dds-user"),
+                    &number.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(unique, vec!["a-2", "a", "a-2-2"]);
+    }
+}
@@ -2,45 +2,105 @@
 export PATH=/opt/opendcs/bin:$PATH
 
 
-mkdir $LRGSHOME/netlist
+mkdir -p $LRGSHOME/netlist
 
-cp /config/.lrgs.passwd $LRGSHOME/.lrgs.passwd
-for user in `cat $LRGSHOME/.lrgs.passwd | cut -d : -f 1 -s`
-do
-    mkdir -p $LRGSHOME/users/$user
-done
+# Netlists exported from a database. The links follow updates of existing files, netlists
+# added or removed later are linked again by watch_config.
+link_netlists() {
+    for link in $LRGSHOME/netlist/*.nl
+    do
+        [ -L "$link" ] && [ ! -e "$link" ] && rm -f "$link"
+    done
+    for netlist in /netlists/*.nl
+    do
+        [ -e "$netlist" ] && ln -sf "$netlist" $LRGSHOME/netlist/
+    done
+}
 
-# Handle DDS config replication
-if [ "${LRGS_INDEX}" != "0" ]
-then
-    LAST_INDEX=`grep number /config/ddsrecv.conf | tail -1 | sed 's/.*"\(\d*\)".*/\1/'`
-    if [ "$LAST_INDEX" == "" ]
+netlists_checksum() {
+    ls /netlists/*.nl 2>/dev/null | cksum
+}
+
+install_password_file() {
+    cp /config/.lrgs.passwd $LRGSHOME/.lrgs.passwd.new
+    mv $LRGSHOME/.lrgs.passwd.new $LRGSHOME/.lrgs.passwd
+    for user in `cat $LRGSHOME/.lrgs.passwd | cut -d : -f 1 -s`
+    do
+        mkdir -p $LRGSHOME/users/$user
+    done
+}
+
+install_receiver_config() {
+    # Handle DDS config replication
+    if [ "${LRGS_INDEX}" != "0" ]
     then
-        LAST_INDEX=-1
-    fi
-    LAST_INDEX=$((LAST_INDEX+1))
+        LAST_INDEX=`grep number /config/ddsrecv.conf | tail -1 | sed 's/.*"\(\d*\)".*/\1/'`
+        if [ "$LAST_INDEX" == "" ]
+        then
+            LAST_INDEX=-1
+        fi
+        LAST_INDEX=$((LAST_INDEX+1))
 
-    target_host=`hostname | sed 's/\(.*\)-\d*$/\1-0/'`
+        target_host=`hostname | sed 's/\(.*\)-\d*$/\1-0/'`
 
-    replication_connection="<connection number="$LAST_INDEX" host="$target_host"> \
-    <enabled>true</enabled> \
-    <port>16003</port> \
-    <name>replication</name> \
-    <username>replication</username> \
-    <authenticate>true</authenticate> \
-</connection>"
+        replication_connection="<connection number="$LAST_INDEX" host="$target_host"> \
+        <enabled>true</enabled> \
+        <port>16003</port> \
+        <name>replication</name> \
+        <username>replication</username> \
+        <authenticate>true</authenticate> \
+    </connection>"
 
-    if grep "</ddsrecvconf>" /config/ddsrecv.conf
-    then
-        sed "/<\/ddsrecvconf>/i \
+        if grep "</ddsrecvconf>" /config/ddsrecv.conf
+        then
+            sed "/<\/ddsrecvconf>/i \
 ${replication_connection} \
-" /config/ddsrecv.conf > /tmp/ddsrecv.conf
+" /config/ddsrecv.conf > /tmp/ddsrecv.conf.new
+        else
+            sed "s/<ddsrecvconf \/>/<ddsrecvconf>${replication_connection}<\/ddsrecvconf>/" /config/ddsrecv.conf > /tmp/ddsrecv.conf.new
+        fi
     else
-        sed "s/<ddsrecvconf \/>/<ddsrecvconf>${replication_connection}<\/ddsrecvconf>/" /config/ddsrecv.conf > /tmp/ddsrecv.conf
+        cp /config/ddsrecv.conf /tmp/ddsrecv.conf.new
     fi
-else
-    cp /config/ddsrecv.conf /tmp/ddsrecv.conf
-fi
+    mv /tmp/ddsrecv.conf.new /tmp/ddsrecv.conf
+    cp /config/drgsconf.xml $LRGSHOME/drgsconf.xml.new
+    mv $LRGSHOME/drgsconf.xml.new $LRGSHOME/drgsconf.xml
+}
+
+config_checksum() {
+    cat /config/.lrgs.passwd /config/ddsrecv.conf /config/drgsconf.xml | cksum
+}
+
+# The kubelet updates the mounted configuration in place. LRGS re-reads the password file and
+# receiver configs while running, so changes are copied into place instead of restarting.
+# Only lrgs.conf, and this script, roll the pods.
+watch_config() {
+    last=`config_checksum`
+    last_netlists=`netlists_checksum`
+    while sleep ${CONFIG_POLL_SECONDS:-10}
+    do
+        current_netlists=`netlists_checksum`
+        if [ "$current_netlists" != "$last_netlists" ]
+        then
+            echo "Netlists changed, relinking"
+            link_netlists
+            last_netlists=$current_netlists
+        fi
+        current=`config_checksum`
+        if [ "$current" != "$last" ]
+        then
+            echo "Configuration changed, reloading password file and receiver configs"
+            install_password_file
+            install_receiver_config
+            last=$current
+        fi
+    done
+}
+
+link_netlists
+install_password_file
+install_receiver_config
+watch_config &
 
 DH=$DCSTOOL_HOME
 
@@ -1,6 +1,9 @@
+pub mod cache;
 pub mod config;
 pub mod configmap;
 pub mod controller;
+pub mod legacy;
 pub mod password_file;
 pub mod service;
 pub mod statefulset;
+pub mod xml;
@@ -8,14 +8,10 @@ pub struct DdsUser {
 
 impl std::fmt::Display for DdsUser {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "({},{},{:?})", self.username, "*********", &self.roles)
+        write!(f, "({},*********,{:?})", self.username, &self.roles)
     }
 }
 
-pub struct PasswordFile {
-    users: Vec<DdsUser>,
-}
-
 fn to_line(user: &DdsUser) -> String {
     let pw_hash = lrgs_password_hash(&user.username, &user.password);
     let roles = if user.roles.is_empty() {
@@ -26,6 +22,11 @@ fn to_line(user: &DdsUser) -> String {
     format!("{}:{roles}:{pw_hash}:", &user.username).to_string()
 }
 
+#[derive(Default)]
+pub struct PasswordFile {
+    users: Vec<DdsUser>,
+}
+
 impl PasswordFile {
     pub fn new() -> PasswordFile {
         PasswordFile { users: vec![] }
@@ -35,15 +36,48 @@ impl PasswordFile {
         self.users.push(user);
     }
 
-    pub fn to_string(&self) -> String {
+    /// Contents of the .lrgs.passwd file.
+    pub fn contents(&self) -> String {
         let mut buffer = String::new();
         for user in self.users.as_slice() {
             buffer.push_str(format!("{}\n", to_line(user)).as_str());
         }
-        return buffer;
+        buffer
     }
 }
 
+/// A line of an existing .lrgs.passwd file. Only the hash of the password is kept there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasswordEntry {
+    pub username: String,
+    pub roles: Vec<String>,
+    pub hash: String,
+}
+
+/// Read the `username:roles:hash:` lines of a .lrgs.passwd file, skipping blank lines and
+/// comments.
+pub fn parse_password_file(contents: &str) -> Vec<PasswordEntry> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let username = fields.next().filter(|u| !u.is_empty())?.to_string();
+            let roles = match fields.next().unwrap_or_default() {
+                "" | "none" => vec![],
+                roles => roles.split(',').map(String::from).collect(),
+            };
+            let hash = fields.next().unwrap_or_default().to_string();
+            Some(PasswordEntry {
+                username,
+                roles,
+                hash,
+            })
+        })
+        .collect()
+}
+
 impl std::fmt::Display for PasswordFile {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "PasswordFile(users = [")?;
@@ -5,13 +5,9 @@ use k8s_openapi::{
     api::core::v1::{Service, ServicePort, ServiceSpec},
     apimachinery::pkg::{apis::meta::v1::OwnerReference, util::intstr::IntOrString},
 };
-use kube::{Client, api::ObjectMeta, runtime::reflector::Lookup};
+use kube::{api::ObjectMeta, runtime::reflector::Lookup};
 
-pub fn create_service(
-    _client: Client,
-    lrgs_cluster: &LrgsCluster,
-    owner_ref: &OwnerReference,
-) -> Vec<Service> {
+pub fn create_service(lrgs_cluster: &LrgsCluster, owner_ref: &OwnerReference) -> Vec<Service> {
     let cluster_name = lrgs_cluster.name().unwrap();
     let ns: Option<String> = lrgs_cluster.metadata.namespace.clone();
     vec![
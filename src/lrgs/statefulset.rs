@@ -17,8 +17,14 @@ use kube::{Resource, ResourceExt, api::ObjectMeta};
 
 use std::collections::BTreeMap;
 
-use crate::api::{constants::LRGS_GROUP, v1::lrgs::LrgsCluster};
+use crate::{
+    api::{constants::LRGS_GROUP, v1::lrgs::LrgsCluster},
+    lrgs::configmap::netlists_config_map_name,
+};
 
+/// `config_hash` covers only the configuration LRGS reads at startup, other changes to the
+/// configuration Secret reach running pods through the volume. The netlists of the cluster
+/// are mounted at /netlists and linked into the LRGS netlist directory by lrgs.sh.
 pub fn create_statefulset(
     lrgs_spec: &LrgsCluster,
     config_hash: String,
@@ -135,6 +141,12 @@ fn pod_spec_template(
                         mount_path: "/config".to_string(),
                         ..Default::default()
                     },
+                    VolumeMount {
+                        name: "netlists".to_string(),
+                        mount_path: "/netlists".to_string(),
+                        read_only: Some(true),
+                        ..Default::default()
+                    },
                 ]),
                 ..Default::default()
             }],
@@ -155,6 +167,16 @@ fn pod_spec_template(
                     }),
                     ..Default::default()
                 },
+                Volume {
+                    name: "netlists".to_string(),
+                    config_map: Some(ConfigMapVolumeSource {
+                        name: netlists_config_map_name(&owner_ref.name),
+                        // Optional so pods start before the controller has written it.
+                        optional: Some(true),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
             ]),
             security_context: Some(PodSecurityContext {
                 fs_group: Some(1000),
@@ -0,0 +1,209 @@
+//! Minimal XML reader for the LRGS configuration files. Handles elements, attributes, text,
+//! comments, CDATA and the predefined and numeric entities; DTDs and namespaces are ignored.
+
+use anyhow::{Result, anyhow, bail};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// Text directly inside the element, trimmed.
+    pub text: String,
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    /// Text of the named child, if present and not empty.
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name)
+            .map(|c| c.text.as_str())
+            .filter(|t| !t.is_empty())
+    }
+}
+
+/// Parse a document, returning its root element.
+pub fn parse(document: &str) -> Result<Element> {
+    let mut reader = Reader {
+        input: document,
+        pos: 0,
+    };
+    reader.skip_misc()?;
+    let root = reader.element()?;
+    reader.skip_misc()?;
+    if reader.pos < reader.input.len() {
+        bail!(
+            "unexpected content after the root element at {}",
+            reader.pos
+        );
+    }
+    Ok(root)
+}
+
+struct Reader<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skip to just past `end`.
+    fn skip_past(&mut self, end: &str) -> Result<&'a str> {
+        let start = self.pos;
+        let found = self.rest().find(end).ok_or(anyhow!("missing {end}"))?;
+        self.pos += found + end.len();
+        Ok(&self.input[start..start + found])
+    }
+
+    /// Skip whitespace, comments, processing instructions and doctype declarations.
+    fn skip_misc(&mut self) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest().starts_with("<!") && !self.rest().starts_with("<![CDATA[") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/' || c == '=')
+            .unwrap_or(rest.len());
+        if end == 0 {
+            bail!("expected a name at {}", self.pos);
+        }
+        self.pos += end;
+        Ok(rest[..end].to_string())
+    }
+
+    fn element(&mut self) -> Result<Element> {
+        if !self.rest().starts_with('<') {
+            bail!("expected an element at {}", self.pos);
+        }
+        self.pos += 1;
+        let mut element = Element {
+            name: self.name()?,
+            ..Default::default()
+        };
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let name = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                bail!("attribute {name} of <{}> has no value", element.name);
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = self
+                .rest()
+                .chars()
+                .next()
+                .filter(|c| *c == '"' || *c == '\'')
+                .ok_or(anyhow!(
+                    "attribute {name} of <{}> is not quoted",
+                    element.name
+                ))?;
+            self.pos += 1;
+            let value = self.skip_past(&quote.to_string())?;
+            element.attributes.push((name, unescape(value)?));
+        }
+
+        let mut text = String::new();
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                bail!("<{}> is not closed", element.name);
+            }
+            if rest.starts_with("</") {
+                self.pos += 2;
+                let name = self.name()?;
+                if name != element.name {
+                    bail!("<{}> closed by </{name}>", element.name);
+                }
+                self.skip_whitespace();
+                self.skip_past(">")?;
+                break;
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                text.push_str(self.skip_past("]]>")?);
+            } else if rest.starts_with("<!--") || rest.starts_with("<?") {
+                self.skip_misc()?;
+            } else if rest.starts_with('<') {
+                element.children.push(self.element()?);
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                text.push_str(&unescape(&rest[..end])?);
+                self.pos += end;
+            }
+        }
+        element.text = text.trim().to_string();
+        Ok(element)
+    }
+}
+
+fn unescape(text: &str) -> Result<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or(anyhow!("unterminated entity in {text}"))?;
+        let entity = &rest[start + 1..start + end];
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32)
+                .ok_or(anyhow!("unknown entity &{entity};"))?,
+        };
+        unescaped.push(c);
+        rest = &rest[start + end + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
@@ -0,0 +1,129 @@
+use std::{io::Read, path::PathBuf};
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use opendcs_controllers::{
+    lrgs::legacy::{ClusterSettings, LegacyFiles, import},
+    render::{Manifests, render, to_yaml},
+};
+
+/// Tools for working with OpenDCS operator resources outside of a cluster.
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print every Secret, ConfigMap, Service, StatefulSet and Job the controllers would
+    /// apply for the LrgsCluster, DdsConnection, DrgsConnection, Netlist, OpenDcsDatabase
+    /// and Secret manifests given.
+    Render {
+        /// Manifest files, - reads standard input.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Namespace for manifests that do not set one.
+        #[arg(long, short, default_value = "default")]
+        namespace: String,
+    },
+    /// Print LrgsCluster, DdsConnection, DrgsConnection and DDS user manifests for an existing
+    /// LRGS installation. Users get new passwords as the old ones are only stored as hashes,
+    /// the operator replaces them once applied. Hand them out from the user Secrets.
+    ImportLegacy {
+        /// LRGS home directory, the files below default to their usual names in it.
+        #[arg(long)]
+        lrgs_home: Option<PathBuf>,
+        #[arg(long)]
+        ddsrecv_conf: Option<PathBuf>,
+        #[arg(long)]
+        drgsconf_xml: Option<PathBuf>,
+        #[arg(long)]
+        lrgs_conf: Option<PathBuf>,
+        #[arg(long)]
+        lrgs_passwd: Option<PathBuf>,
+        /// Name of the LrgsCluster.
+        #[arg(long, default_value = "main")]
+        name: String,
+        #[arg(long, short)]
+        namespace: Option<String>,
+        #[arg(long, default_value_t = 1)]
+        replicas: i32,
+        #[arg(long, default_value = "standard")]
+        storage_class: String,
+        #[arg(long, default_value = "30Gi")]
+        storage_size: String,
+    },
+}
+
+/// Contents of `file`, or of `default` in `home` when that exists.
+fn legacy_file(
+    file: Option<PathBuf>,
+    home: &Option<PathBuf>,
+    default: &str,
+) -> anyhow::Result<Option<String>> {
+    let path = match (file, home) {
+        (Some(file), _) => file,
+        (None, Some(home)) if home.join(default).exists() => home.join(default),
+        _ => return Ok(None),
+    };
+    let contents =
+        std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    Ok(Some(contents))
+}
+
+fn main() -> anyhow::Result<()> {
+    match Cli::parse().command {
+        Command::Render { files, namespace } => {
+            let mut manifests = Manifests::default();
+            for file in files {
+                let yaml = if file.as_os_str() == "-" {
+                    let mut yaml = String::new();
+                    std::io::stdin().read_to_string(&mut yaml)?;
+                    yaml
+                } else {
+                    std::fs::read_to_string(&file)
+                        .with_context(|| format!("reading {}", file.display()))?
+                };
+                manifests
+                    .parse(&yaml, &namespace)
+                    .with_context(|| format!("parsing {}", file.display()))?;
+            }
+            print!("{}", to_yaml(&render(&manifests)?)?);
+        }
+        Command::ImportLegacy {
+            lrgs_home,
+            ddsrecv_conf,
+            drgsconf_xml,
+            lrgs_conf,
+            lrgs_passwd,
+            name,
+            namespace,
+            replicas,
+            storage_class,
+            storage_size,
+        } => {
+            let files = LegacyFiles {
+                ddsrecv_conf: legacy_file(ddsrecv_conf, &lrgs_home, "ddsrecv.conf")?,
+                drgsconf_xml: legacy_file(drgsconf_xml, &lrgs_home, "drgsconf.xml")?,
+                lrgs_conf: legacy_file(lrgs_conf, &lrgs_home, "lrgs.conf")?,
+                lrgs_passwd: legacy_file(lrgs_passwd, &lrgs_home, ".lrgs.passwd")?,
+            };
+            let imported = import(
+                &files,
+                &ClusterSettings {
+                    name,
+                    namespace,
+                    replicas,
+                    storage_class,
+                    storage_size,
+                },
+            )?;
+            for warning in &imported.warnings {
+                eprintln!("warning: {warning}");
+            }
+            print!("{}", to_yaml(&imported.objects()?)?);
+        }
+    }
+    Ok(())
+}
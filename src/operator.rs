@@ -0,0 +1,250 @@
+//! Runs any subset of the controllers behind one health, metrics and diagnostics server.
+
+use std::{collections::BTreeMap, future::Future, pin::Pin, sync::Arc};
+
+use actix_web::{
+    App, HttpRequest, HttpResponse, HttpServer, Responder, get, middleware, web::Data,
+};
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+use futures::future::try_join_all;
+use kube::{Client, ResourceExt};
+use prometheus_client::registry::Registry;
+use tokio::sync::RwLock;
+
+use crate::{
+    apps::{api, computation, import, netlist, routing},
+    leader::LeaderElection,
+    lrgs, schema,
+    settings::{ControllerArgs, Settings},
+    telemetry::{
+        metrics::{METRICS_PREFIX, MetricFamilies},
+        state::{Diagnostics, State},
+        telemetry,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ControllerKind {
+    /// LrgsClusters.
+    Lrgs,
+    /// OpenDcsDatabase schema migrations.
+    Schema,
+    /// Computation processes, routing schedulers, APIs, imports and netlists.
+    Apps,
+}
+
+impl ControllerKind {
+    /// Each kind leads on its own Lease, so combined and single controller deployments can
+    /// run side by side.
+    fn lease_name(&self) -> &'static str {
+        match self {
+            ControllerKind::Lrgs => "opendcs-lrgs-controller",
+            ControllerKind::Schema => "opendcs-schema-controller",
+            ControllerKind::Apps => "opendcs-apps-controller",
+        }
+    }
+}
+
+/// Run the OpenDCS controllers.
+#[derive(Parser, Debug)]
+#[command(name = "opendcs-operator")]
+pub struct OperatorArgs {
+    /// Controllers to run, comma separated.
+    #[arg(
+        long,
+        env = "OPENDCS_CONTROLLERS",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "lrgs,schema,apps"
+    )]
+    pub controllers: Vec<ControllerKind>,
+    #[command(flatten)]
+    pub controller: ControllerArgs,
+}
+
+/// What the web server reads: the shared metrics registry and the diagnostics of each
+/// controller by name.
+struct Served {
+    registry: Registry,
+    families: MetricFamilies,
+    diagnostics: BTreeMap<String, Arc<RwLock<Diagnostics>>>,
+}
+
+impl Served {
+    fn state<T: Clone + ResourceExt>(&mut self, name: &str, settings: &Settings) -> State<T> {
+        let state = State::for_controller(&self.families, name).with_settings(settings.clone());
+        self.diagnostics
+            .insert(name.to_string(), state.diagnostics.clone());
+        state
+    }
+}
+
+type ControllerFuture = Pin<Box<dyn Future<Output = Result<()>>>>;
+
+/// Run the selected controllers, each once this replica leads it, until they shut down or
+/// one loses its lease.
+pub async fn run(kinds: &[ControllerKind], args: ControllerArgs) -> Result<()> {
+    telemetry::init(args.log_format).await;
+    rustls::crypto::ring::default_provider()
+        .install_default()
+        .expect("Failed to install rustls crypto provider");
+    let client = Client::try_default()
+        .await
+        .expect("failed to create kube Client");
+    let settings = args.settings();
+    let mut registry = Registry::with_prefix(METRICS_PREFIX);
+    let families = MetricFamilies::register(&mut registry);
+    let mut served = Served {
+        registry,
+        families,
+        diagnostics: BTreeMap::new(),
+    };
+
+    let mut controllers: Vec<ControllerFuture> = Vec::new();
+    for kind in ControllerKind::value_variants()
+        .iter()
+        .filter(|k| kinds.contains(k))
+    {
+        let election = LeaderElection::new(client.clone(), kind.lease_name());
+        let client = client.clone();
+        match kind {
+            ControllerKind::Lrgs => {
+                let state = served.state("lrgs", &settings);
+                let election = election.with_diagnostics(state.diagnostics.clone());
+                controllers.push(Box::pin(election.run(lrgs::controller::run(state, client))));
+            }
+            ControllerKind::Schema => {
+                let state = served.state("schema", &settings);
+                let election = election.with_diagnostics(state.diagnostics.clone());
+                controllers.push(Box::pin(
+                    election.run(schema::controller::run(state, client)),
+                ));
+            }
+            ControllerKind::Apps => {
+                let computation = served.state("computation", &settings);
+                let routing = served.state("routing", &settings);
+                let api = served.state("api", &settings);
+                let import = served.state("import", &settings);
+                let netlist = served.state("netlist", &settings);
+                let election = [&computation.diagnostics, &routing.diagnostics]
+                    .into_iter()
+                    .chain([&api.diagnostics, &import.diagnostics, &netlist.diagnostics])
+                    .fold(election, |e, d| e.with_diagnostics(d.clone()));
+                let apps = async move {
+                    tokio::join!(
+                        computation::run(computation, client.clone()),
+                        routing::run(routing, client.clone()),
+                        api::run(api, client.clone()),
+                        import::run(import, client.clone()),
+                        netlist::run(netlist, client),
+                    );
+                };
+                controllers.push(Box::pin(election.run(apps)));
+            }
+        }
+    }
+
+    let data = Data::new(served);
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .wrap(middleware::Logger::default().exclude("/health"))
+            .service(index)
+            .service(health)
+            .service(metrics)
+    })
+    .workers(args.http_workers)
+    .bind(&args.bind_address)?
+    .shutdown_timeout(5)
+    .run();
+    let handle = server.handle();
+    let (controllers, server) = tokio::join!(
+        async {
+            // Standby replicas keep serving health and metrics while waiting for their lease.
+            let result = try_join_all(controllers).await;
+            handle.stop(true).await;
+            result
+        },
+        server
+    );
+    server?;
+    controllers?;
+    Ok(())
+}
+
+#[get("/metrics")]
+async fn metrics(c: Data<Served>, _req: HttpRequest) -> impl Responder {
+    let mut metrics = String::new();
+    prometheus_client::encoding::text::encode(&mut metrics, &c.registry).unwrap();
+    HttpResponse::Ok()
+        .content_type("application/openmetrics-text; version=1.0.0; charset=utf-8")
+        .body(metrics)
+}
+
+#[get("/health")]
+async fn health(_: HttpRequest) -> impl Responder {
+    HttpResponse::Ok().json("healthy")
+}
+
+#[get("/")]
+async fn index(c: Data<Served>, _req: HttpRequest) -> impl Responder {
+    let mut diagnostics = BTreeMap::new();
+    for (name, d) in &c.diagnostics {
+        diagnostics.insert(name.clone(), d.read().await.clone());
+    }
+    HttpResponse::Ok().json(&diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::v1::{lrgs::LrgsCluster, tsdb::database::OpenDcsDatabase};
+
+    #[test]
+    fn controllers_default_to_all() {
+        let args = OperatorArgs::parse_from(["opendcs-operator"]);
+        assert_eq!(
+            args.controllers,
+            vec![
+                ControllerKind::Lrgs,
+                ControllerKind::Schema,
+                ControllerKind::Apps
+            ]
+        );
+        let args = OperatorArgs::parse_from(["opendcs-operator", "--controllers", "lrgs,apps"]);
+        assert_eq!(
+            args.controllers,
+            vec![ControllerKind::Lrgs, ControllerKind::Apps]
+        );
+    }
+
+    #[test]
+    fn controllers_share_one_registry() {
+        let mut registry = Registry::with_prefix(METRICS_PREFIX);
+        let families = MetricFamilies::register(&mut registry);
+        let mut served = Served {
+            registry,
+            families,
+            diagnostics: BTreeMap::new(),
+        };
+        let settings = Settings::default();
+        let lrgs: State<LrgsCluster> = served.state("lrgs", &settings);
+        let _schema: State<OpenDcsDatabase> = served.state("schema", &settings);
+        lrgs.metrics.reconcile.runs.inc();
+        let mut encoded = String::new();
+        prometheus_client::encoding::text::encode(&mut encoded, &served.registry).unwrap();
+        assert!(encoded.contains("ctrl_reconcile_runs_total{controller=\"lrgs\"} 1"));
+        assert!(encoded.contains("ctrl_reconcile_runs_total{controller=\"schema\"} 0"));
+        assert_eq!(
+            encoded
+                .matches("# TYPE ctrl_reconcile_runs counter")
+                .count(),
+            1
+        );
+        assert_eq!(
+            served.diagnostics.keys().collect::<Vec<_>>(),
+            vec!["lrgs", "schema"]
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! Rendering of the objects the controllers apply from manifests on disk, without a cluster.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use k8s_openapi::{ByteString, api::core::v1::Secret};
+use kube::{Resource, ResourceExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    api::v1::{
+        dds_recv::DdsConnection, drgs::DrgsConnection, lrgs::LrgsCluster, netlist::Netlist,
+        tsdb::database::OpenDcsDatabase,
+    },
+    lrgs::{
+        config::{
+            DDS_USER_TYPE, LrgsInputs, MANAGED_USERS, managed_user_secret, render_lrgs_config,
+        },
+        configmap::{created_script_config_map, netlists_config_map},
+        service::create_service,
+        statefulset::create_statefulset,
+    },
+    schema::{
+        client_config::render_client_config,
+        job::{create_migration_job, migration_job_label, with_job_settings},
+    },
+};
+
+/// Uid given to manifests without one, owner references need it.
+pub const RENDER_UID: &str = "00000000-0000-0000-0000-000000000000";
+
+/// Password shown for managed users the operator would generate.
+pub const GENERATED_PASSWORD: &str = "<generated>";
+
+/// Custom resources and Secrets read from manifests.
+#[derive(Clone, Debug, Default)]
+pub struct Manifests {
+    pub lrgs_clusters: Vec<LrgsCluster>,
+    pub dds_connections: Vec<DdsConnection>,
+    pub drgs_connections: Vec<DrgsConnection>,
+    pub netlists: Vec<Netlist>,
+    pub databases: Vec<OpenDcsDatabase>,
+    pub secrets: Vec<Secret>,
+}
+
+impl Manifests {
+    /// Add every document of a multi-document YAML stream. Kinds the operator does not
+    /// read are skipped, objects without a namespace are placed in `namespace`.
+    pub fn parse(&mut self, yaml: &str, namespace: &str) -> Result<()> {
+        for document in serde_yaml::Deserializer::from_str(yaml) {
+            let value = Value::deserialize(document)?;
+            if value.is_null() {
+                continue;
+            }
+            match value.get("kind").and_then(Value::as_str) {
+                Some("LrgsCluster") => self.lrgs_clusters.push(object(value, namespace)?),
+                Some("DdsConnection") => self.dds_connections.push(object(value, namespace)?),
+                Some("DrgsConnection") => self.drgs_connections.push(object(value, namespace)?),
+                Some("Netlist") => self.netlists.push(object(value, namespace)?),
+                Some("OpenDcsDatabase") => self.databases.push(object(value, namespace)?),
+                Some("Secret") => self.secrets.push(secret(object(value, namespace)?)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn secrets_in(&self, namespace: &str) -> impl Iterator<Item = &Secret> {
+        self.secrets
+            .iter()
+            .filter(move |s| s.namespace().as_deref() == Some(namespace))
+    }
+
+    fn secret(&self, namespace: &str, name: &str) -> Option<&Secret> {
+        self.secrets_in(namespace).find(|s| s.name_any() == name)
+    }
+}
+
+fn object<K: Resource + for<'de> Deserialize<'de>>(value: Value, namespace: &str) -> Result<K> {
+    let mut object: K = serde_json::from_value(without_nulls(value))?;
+    let meta = object.meta_mut();
+    if meta.namespace.is_none() {
+        meta.namespace = Some(namespace.to_string());
+    }
+    if meta.uid.is_none() {
+        meta.uid = Some(RENDER_UID.to_string());
+    }
+    Ok(object)
+}
+
+/// Empty values, like `roles:` in a Secret, are left out as the API server does.
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k, without_nulls(v)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
+
+/// Merge stringData into data as the API server does.
+fn secret(mut secret: Secret) -> Secret {
+    if let Some(string_data) = secret.string_data.take() {
+        let data = secret.data.get_or_insert_with(BTreeMap::new);
+        for (key, value) in string_data {
+            data.insert(key, ByteString(value.into_bytes()));
+        }
+    }
+    secret
+}
+
+fn push<T: Serialize>(rendered: &mut Vec<Value>, object: T) -> Result<()> {
+    rendered.push(serde_json::to_value(object)?);
+    Ok(())
+}
+
+/// Secrets, ConfigMap, Services and StatefulSet the lrgs controller applies for a cluster.
+/// Managed users missing from the manifests are shown with a placeholder password.
+pub fn render_lrgs_cluster(manifests: &Manifests, cluster: &LrgsCluster) -> Result<Vec<Value>> {
+    let namespace = cluster.namespace().unwrap_or("default".to_string());
+    let in_namespace = |ns: Option<String>| ns.as_deref() == Some(namespace.as_str());
+    let owner_ref = cluster
+        .controller_owner_ref(&())
+        .ok_or(anyhow!("LrgsCluster {} has no uid", cluster.name_any()))?;
+    let mut rendered = Vec::new();
+
+    let mut users: Vec<Secret> = manifests
+        .secrets_in(&namespace)
+        .filter(|s| s.type_.as_deref() == Some(DDS_USER_TYPE))
+        .cloned()
+        .collect();
+    for user in MANAGED_USERS {
+        if manifests.secret(&namespace, user).is_none() {
+            let secret = managed_user_secret(cluster, &owner_ref, user, GENERATED_PASSWORD);
+            users.push(secret.clone());
+            push(&mut rendered, secret)?;
+        }
+    }
+    let inputs = LrgsInputs {
+        dds_connections: manifests
+            .dds_connections
+            .iter()
+            .filter(|c| in_namespace(c.namespace()))
+            .cloned()
+            .collect(),
+        drgs_connections: manifests
+            .drgs_connections
+            .iter()
+            .filter(|c| in_namespace(c.namespace()))
+            .cloned()
+            .collect(),
+        users,
+    };
+    let lrgs_config = render_lrgs_config(cluster, &owner_ref, &inputs)?;
+    let (script_config_map, script_hash) = created_script_config_map(namespace.clone(), &owner_ref);
+    // Netlists are exported in the cluster, the rendered ConfigMap starts out empty.
+    let netlists_config_map = netlists_config_map(namespace.clone(), &owner_ref, &[]);
+    let statefulset = create_statefulset(cluster, lrgs_config.restart_hash(), script_hash);
+    push(&mut rendered, lrgs_config.secret)?;
+    push(&mut rendered, script_config_map)?;
+    push(&mut rendered, netlists_config_map)?;
+    push(&mut rendered, statefulset)?;
+    for service in create_service(cluster, &owner_ref) {
+        push(&mut rendered, service)?;
+    }
+    Ok(rendered)
+}
+
+/// Client ConfigMap and Secret, once both database secrets are in the manifests, and the
+/// migration Job the schema controller applies for a database.
+pub fn render_database(manifests: &Manifests, database: &OpenDcsDatabase) -> Result<Vec<Value>> {
+    let namespace = database.namespace().unwrap_or("default".to_string());
+    let mut rendered = Vec::new();
+    if let (Some(admin), Some(app_user)) = (
+        manifests.secret(&namespace, &database.spec.database_secret),
+        manifests.secret(&namespace, &format!("{}-app-user", database.name_any())),
+    ) {
+        let (config_map, credentials) = render_client_config(database, admin, app_user)?;
+        push(&mut rendered, config_map)?;
+        push(&mut rendered, credentials)?;
+    }
+    // The controller appends a timestamp to the Job name, left out to keep output stable.
+    let label = migration_job_label(database);
+    let job = create_migration_job(database, &label, ("migration-job", label.clone()), vec![]);
+    push(&mut rendered, with_job_settings(database, job))?;
+    Ok(rendered)
+}
+
+/// Everything the controllers would apply for the manifests.
+pub fn render(manifests: &Manifests) -> Result<Vec<Value>> {
+    let mut rendered = Vec::new();
+    for cluster in &manifests.lrgs_clusters {
+        rendered.extend(render_lrgs_cluster(manifests, cluster)?);
+    }
+    for database in &manifests.databases {
+        rendered.extend(render_database(manifests, database)?);
+    }
+    Ok(rendered)
+}
+
+/// Objects as a multi-document YAML stream.
+pub fn to_yaml(objects: &[Value]) -> Result<String> {
+    let mut yaml = String::new();
+    for object in objects {
+        yaml.push_str("---\n");
+        yaml.push_str(&serde_yaml::to_string(object)?);
+    }
+    Ok(yaml)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k8s_openapi::{
+        api::apps::v1::StatefulSet, apimachinery::pkg::apis::meta::v1::OwnerReference,
+    };
+
+    fn owner_ref() -> OwnerReference {
+        OwnerReference {
+            name: "main".to_string(),
+            ..Default::default()
+        }
+    }
+
+    const MANIFESTS: &str = r#"
+apiVersion: lrgs.opendcs.org/v1
+kind: LrgsCluster
+metadata:
+  name: main
+spec:
+  replicas: 1
+  storageClass: standard
+  storageSize: 1Gi
+---
+apiVersion: lrgs.opendcs.org/v1
+kind: DdsConnection
+metadata:
+  name: upstream
+spec:
+  hostname: upstream.test
+  username: testuser
+---
+apiVersion: v1
+kind: Secret
+type: lrgs.opendcs.org/ddsuser
+metadata:
+  name: lrgsadmin
+stringData:
+  username: lrgsadmin
+  password: secret
+  roles:
+"#;
+
+    #[test]
+    fn lrgs_cluster_renders_from_manifests() {
+        let mut manifests = Manifests::default();
+        manifests.parse(MANIFESTS, "lrgs").unwrap();
+        let rendered = render(&manifests).unwrap();
+        let names: Vec<(&str, &str)> = rendered
+            .iter()
+            .map(|o| {
+                (
+                    o["kind"].as_str().unwrap(),
+                    o["metadata"]["name"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("Secret", "replication"),
+                ("Secret", "routing-user"),
+                ("Secret", "main-lrgs-configuration"),
+                ("ConfigMap", "main-lrgs-scripts"),
+                ("ConfigMap", "main-lrgs-netlists"),
+                ("StatefulSet", "main-lrgs"),
+                ("Service", "main-lrgs-service"),
+                ("Service", "main-lrgs-service-headless"),
+            ]
+        );
+        assert!(
+            rendered
+                .iter()
+                .all(|o| o["metadata"]["namespace"] == "lrgs")
+        );
+
+        let config: Secret = serde_json::from_value(rendered[2].clone()).unwrap();
+        let files = config.data.unwrap();
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            vec![".lrgs.passwd", "ddsrecv.conf", "drgsconf.xml", "lrgs.conf"]
+        );
+        let file = |name: &str| String::from_utf8(files[name].0.clone()).unwrap();
+        let ddsrecv = file("ddsrecv.conf");
+        assert!(ddsrecv.contains(r#"<connection number="0" host="upstream.test">"#));
+        assert!(ddsrecv.contains("<username>testuser</username>"));
+        let users: Vec<String> = file(".lrgs.passwd")
+            .lines()
+            .map(|line| line.split(':').next().unwrap().to_string())
+            .collect();
+        assert_eq!(users, vec!["lrgsadmin", "replication", "routing-user"]);
+
+        let statefulset: StatefulSet = serde_json::from_value(rendered[5].clone()).unwrap();
+        let template = statefulset.spec.unwrap().template;
+        let annotations = template.metadata.unwrap().annotations.unwrap();
+        let (_, script_hash) = created_script_config_map("lrgs".to_string(), &owner_ref());
+        assert_eq!(
+            annotations["lrgs.opendcs.org/lrgs-script-hash"],
+            script_hash
+        );
+        assert!(annotations.contains_key("lrgs.opendcs.org/lrgs-config-hash"));
+        let pod = template.spec.unwrap();
+        assert_eq!(
+            pod.containers[0].image.as_deref(),
+            Some("ghcr.io/opendcs/lrgs:7.0.15-RC03")
+        );
+        let netlists = pod
+            .volumes
+            .unwrap()
+            .into_iter()
+            .find(|v| v.name == "netlists");
+        assert_eq!(
+            netlists.and_then(|v| v.config_map).map(|c| c.name),
+            Some("main-lrgs-netlists".to_string())
+        );
+    }
+
+    #[test]
+    fn receiver_changes_keep_the_pod_template() {
+        let mut manifests = Manifests::default();
+        manifests.parse(MANIFESTS, "lrgs").unwrap();
+        let before = render(&manifests).unwrap();
+        manifests.dds_connections[0].spec.hostname = "other.test".to_string();
+        let after = render(&manifests).unwrap();
+        assert_ne!(before[2]["data"], after[2]["data"]);
+        assert_eq!(before[5]["spec"], after[5]["spec"]);
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    api::{
+        constants::TSDB_GROUP,
+        v1::tsdb::database::{BackupSpec, OpenDcsDatabase},
+    },
+    schema::job::{ScriptJob, script_job},
+};
+use k8s_openapi::api::{
+    batch::v1::Job,
+    core::v1::{EnvVar, PersistentVolumeClaimVolumeSource, Volume, VolumeMount},
+};
+use kube::ResourceExt;
+
+/// Label used to find the backup Jobs of a database.
+pub fn backup_job_label(database: &OpenDcsDatabase) -> String {
+    format!("{}-database-backup", database.name_any())
+}
+
+/// Label used to find the restore Jobs of a database.
+pub fn restore_job_label(database: &OpenDcsDatabase) -> String {
+    format!("{}-database-restore", database.name_any())
+}
+
+/// Build a Job that writes a pg_dump of the database to the configured claim.
+/// The Job is named after the backup.
+pub fn create_backup_job(
+    database: &OpenDcsDatabase,
+    backup: &BackupSpec,
+    backup_name: &str,
+    schema_version: &str,
+) -> Job {
+    let script = String::from_utf8(Vec::from(include_bytes!("backup.sh"))).unwrap_or_default();
+    backup_volume_job(
+        database,
+        backup,
+        backup_name,
+        ("backup-job", backup_job_label(database)),
+        BTreeMap::from([(
+            format!("{}/schema-version", TSDB_GROUP.as_str()),
+            schema_version.to_string(),
+        )]),
+        script,
+        vec![
+            EnvVar {
+                name: "BACKUP_NAME".to_string(),
+                value: Some(backup_name.to_string()),
+                ..Default::default()
+            },
+            EnvVar {
+                name: "BACKUP_RETENTION".to_string(),
+                value: Some(backup.retention.to_string()),
+                ..Default::default()
+            },
+            EnvVar {
+                name: "SCHEMA_VERSION".to_string(),
+                value: Some(schema_version.to_string()),
+                ..Default::default()
+            },
+        ],
+    )
+}
+
+/// Build a Job that restores the named backup over the current database contents.
+pub fn create_restore_job(
+    database: &OpenDcsDatabase,
+    backup: &BackupSpec,
+    job_name: &str,
+    backup_name: &str,
+) -> Job {
+    let script = String::from_utf8(Vec::from(include_bytes!("restore.sh"))).unwrap_or_default();
+    backup_volume_job(
+        database,
+        backup,
+        job_name,
+        ("restore-job", restore_job_label(database)),
+        BTreeMap::from([(
+            format!("{}/backup", TSDB_GROUP.as_str()),
+            backup_name.to_string(),
+        )]),
+        script,
+        vec![EnvVar {
+            name: "BACKUP_NAME".to_string(),
+            value: Some(backup_name.to_string()),
+            ..Default::default()
+        }],
+    )
+}
+
+/// Job running a script with the admin credentials and the backup claim mounted.
+fn backup_volume_job(
+    database: &OpenDcsDatabase,
+    backup: &BackupSpec,
+    job_name: &str,
+    label: (&'static str, String),
+    annotations: BTreeMap<String, String>,
+    script: String,
+    extra_env: Vec<EnvVar>,
+) -> Job {
+    let mut env = vec![EnvVar {
+        name: "BACKUP_DIR".to_string(),
+        value: Some(format!("/backups/{}", database.name_any())),
+        ..Default::default()
+    }];
+    env.extend(extra_env);
+    script_job(
+        database,
+        ScriptJob {
+            job_name: job_name.to_string(),
+            label,
+            annotations,
+            image: backup.image.clone(),
+            script,
+            env,
+            volumes: vec![(
+                Volume {
+                    name: "backups".to_string(),
+                    persistent_volume_claim: Some(PersistentVolumeClaimVolumeSource {
+                        claim_name: backup.persistent_volume_claim.clone(),
+                        read_only: Some(false),
+                    }),
+                    ..Default::default()
+                },
+                VolumeMount {
+                    name: "backups".to_string(),
+                    mount_path: "/backups".to_string(),
+                    ..Default::default()
+                },
+            )],
+        },
+    )
+}
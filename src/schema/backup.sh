@@ -0,0 +1,21 @@
+#!/bin/bash
+set -euo pipefail
+
+# The database secret holds a jdbc uri, pg_dump wants the plain postgresql one.
+PG_URI="${DATABASE_URL#jdbc:}"
+PG_USER=`cat /secrets/db-admin/username`
+export PGPASSWORD=`cat /secrets/db-admin/password`
+
+mkdir -p "${BACKUP_DIR}"
+pg_dump --dbname="${PG_URI}" --username="${PG_USER}" --format=custom \
+    --file="${BACKUP_DIR}/${BACKUP_NAME}.dump.partial"
+mv "${BACKUP_DIR}/${BACKUP_NAME}.dump.partial" "${BACKUP_DIR}/${BACKUP_NAME}.dump"
+echo "${SCHEMA_VERSION}" > "${BACKUP_DIR}/${BACKUP_NAME}.version"
+
+# Only keep the newest BACKUP_RETENTION backups
+ls -1t "${BACKUP_DIR}"/*.dump | tail -n +$((BACKUP_RETENTION+1)) | while read old
+do
+    echo "Removing old backup ${old}"
+    rm -f "${old}" "${old%.dump}.version"
+done
+echo "Backup ${BACKUP_NAME} complete."
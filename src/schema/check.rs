@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    api::v1::tsdb::database::{
+        InstalledSchema, OpenDcsDatabase, OpenDcsDatabaseStatus, POSTGRES_CLIENT_IMAGE, SchemaDrift,
+    },
+    schema::job::{ScriptJob, failed_condition, latest_job, pod_output, run_job, script_job},
+};
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use k8s_openapi::api::batch::v1::Job;
+use kube::{Client, ResourceExt};
+use tracing::{info, warn};
+
+/// How often the schema history of a Ready database is read again.
+pub const CHECK_INTERVAL: Duration = Duration::hours(6);
+
+/// Minimum time between two check Jobs.
+const RETRY_INTERVAL: Duration = Duration::minutes(5);
+
+#[derive(Debug, PartialEq)]
+pub enum CheckProgress {
+    Running,
+    Done,
+    Failed,
+}
+
+fn check_job_label(database: &OpenDcsDatabase) -> String {
+    format!("{}-database-check", database.name_any())
+}
+
+/// Job printing the latest version and a digest of flyway_schema_history.
+pub fn create_check_job(database: &OpenDcsDatabase, job_name: &str) -> Job {
+    let script = String::from_utf8(Vec::from(include_bytes!("check.sh"))).unwrap_or_default();
+    script_job(
+        database,
+        ScriptJob {
+            job_name: job_name.to_string(),
+            label: ("check-job", check_job_label(database)),
+            annotations: BTreeMap::new(),
+            image: POSTGRES_CLIENT_IMAGE.to_string(),
+            script,
+            env: vec![],
+            volumes: vec![],
+        },
+    )
+}
+
+/// Version, checksum and number of migrations from the output of check.sh.
+pub fn parse_check(output: &str) -> Option<(Option<String>, Option<String>, u32)> {
+    let line = output
+        .lines()
+        .map(str::trim)
+        .rfind(|l| l.starts_with("INSTALLED_SCHEMA|"))?;
+    let fields: Vec<&str> = line.split('|').collect();
+    let field = |i: usize| {
+        fields
+            .get(i)
+            .filter(|f| !f.is_empty())
+            .map(|f| f.to_string())
+    };
+    Some((field(1), field(2), fields.get(3)?.parse().ok()?))
+}
+
+/// Read flyway_schema_history once the last check is older than `max_age`, recording the
+/// result in `status.installed_schema`. A changed checksum without a change of
+/// applied_schema_version is recorded as drift.
+pub async fn check_schema(
+    client: &Client,
+    database: &OpenDcsDatabase,
+    status: &mut OpenDcsDatabaseStatus,
+    max_age: Duration,
+) -> Result<CheckProgress> {
+    let namespace = database.namespace().unwrap_or("default".to_string());
+    let latest = latest_job(
+        client.clone(),
+        &namespace,
+        &format!("check-job={}", check_job_label(database)),
+    )
+    .await?;
+    let mut failed = false;
+    if let Some(job) = &latest {
+        let job_name = job.name_any();
+        if job.status.as_ref().and_then(|s| s.succeeded).unwrap_or(0) > 0 {
+            if status.installed_schema.as_ref().map(|i| &i.job_name) != Some(&job_name) {
+                let output = pod_output(client.clone(), &namespace, &job_name, None)
+                    .await
+                    .unwrap_or_default();
+                record(database, status, job_name, &output);
+                return Ok(CheckProgress::Done);
+            }
+        } else if failed_condition(job).is_some() {
+            failed = true;
+        } else {
+            return Ok(CheckProgress::Running);
+        }
+    }
+    let started = latest
+        .as_ref()
+        .and_then(|j| j.metadata.creation_timestamp.clone())
+        .map(|t| Utc::now() - t.0);
+    if started.is_some_and(|age| age < max_age.max(RETRY_INTERVAL)) {
+        return Ok(if failed {
+            CheckProgress::Failed
+        } else {
+            CheckProgress::Done
+        });
+    }
+
+    let job_name = format!(
+        "{}-check-{}",
+        database.name_any(),
+        Utc::now().format("%Y%m%d%H%M%S")
+    );
+    info!("Creating schema check job {}/{}", &namespace, &job_name);
+    run_job(client, database, create_check_job(database, &job_name)).await?;
+    Ok(CheckProgress::Running)
+}
+
+fn record(
+    database: &OpenDcsDatabase,
+    status: &mut OpenDcsDatabaseStatus,
+    job_name: String,
+    output: &str,
+) {
+    let Some((version, checksum, migrations)) = parse_check(output) else {
+        // Keep what is known rather than reporting an unreadable result as drift.
+        warn!(
+            "Unable to read the output of schema check job {}",
+            &job_name
+        );
+        let previous = status.installed_schema.take();
+        status.installed_schema = Some(InstalledSchema {
+            job_name,
+            ..previous.unwrap_or(InstalledSchema {
+                version: None,
+                checksum: None,
+                migrations: 0,
+                schema_version: status.applied_schema_version.clone(),
+                job_name: String::new(),
+                checked: Utc::now(),
+            })
+        });
+        return;
+    };
+    let installed = InstalledSchema {
+        version,
+        checksum,
+        migrations,
+        schema_version: status.applied_schema_version.clone(),
+        job_name,
+        checked: Utc::now(),
+    };
+    match &status.installed_schema {
+        Some(previous)
+            if previous.schema_version == installed.schema_version
+                && previous.checksum != installed.checksum =>
+        {
+            warn!(
+                "Schema history of {}/{} changed outside of the operator.",
+                database.namespace().unwrap_or_default(),
+                database.name_any()
+            );
+            status.schema_drift = Some(SchemaDrift {
+                previous_checksum: previous.checksum.clone(),
+                checksum: installed.checksum.clone(),
+                detected: Utc::now(),
+            });
+        }
+        Some(previous) if previous.schema_version != installed.schema_version => {
+            status.schema_drift = None;
+        }
+        _ => {}
+    }
+    status.installed_schema = Some(installed);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_output_is_parsed() {
+        let output = "INSTALLED_SCHEMA|7.0.14|d41d8cd98f00b204e9800998ecf8427e|12\n";
+        assert_eq!(
+            parse_check(output),
+            Some((
+                Some("7.0.14".to_string()),
+                Some("d41d8cd98f00b204e9800998ecf8427e".to_string()),
+                12
+            ))
+        );
+    }
+
+    #[test]
+    fn missing_history_is_an_empty_schema() {
+        let output = "No flyway_schema_history table found.\nINSTALLED_SCHEMA|||0\n";
+        assert_eq!(parse_check(output), Some((None, None, 0)));
+        assert_eq!(parse_check("psql: error: connection refused"), None);
+    }
+}
@@ -0,0 +1,32 @@
+#!/bin/bash
+set -euo pipefail
+
+# The database secret holds a jdbc uri, psql wants the plain postgresql one.
+PG_URI="${DATABASE_URL#jdbc:}"
+export PGUSER=`cat /secrets/db-admin/username`
+export PGPASSWORD=`cat /secrets/db-admin/password`
+
+run_sql() {
+    psql --dbname="${PG_URI}" --quiet --no-align --tuples-only -v ON_ERROR_STOP=1 "$@"
+}
+
+# Output is read back by the operator: INSTALLED_SCHEMA|<version>|<checksum>|<migrations>
+exists=`run_sql -c "SELECT to_regclass('flyway_schema_history') IS NOT NULL"`
+if [ "${exists}" != "t" ]
+then
+    echo "No flyway_schema_history table found."
+    echo "INSTALLED_SCHEMA|||0"
+    exit 0
+fi
+
+run_sql --field-separator='|' <<'SQL'
+SELECT 'INSTALLED_SCHEMA',
+       (SELECT version FROM flyway_schema_history
+         WHERE success AND version IS NOT NULL
+         ORDER BY installed_rank DESC LIMIT 1),
+       md5(coalesce(string_agg(concat_ws(':', installed_rank, version, description, checksum),
+                               ',' ORDER BY installed_rank), '')),
+       count(*)
+  FROM flyway_schema_history
+ WHERE success;
+SQL
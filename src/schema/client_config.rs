@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+
+use crate::api::{constants::TSDB_GROUP, v1::tsdb::database::OpenDcsDatabase};
+use anyhow::{Result, anyhow};
+use k8s_openapi::{
+    ByteString,
+    api::core::v1::{ConfigMap, Secret},
+};
+use kube::{
+    Api, Client, Resource, ResourceExt,
+    api::{ObjectMeta, Patch, PatchParams},
+};
+use sha2::{Digest, Sha256};
+
+/// ConfigMap holding decodes.properties, and the connection settings as DATABASE_URL,
+/// DATABASE_DRIVER and DATABASE_TYPE, for applications using the database.
+pub fn client_config_name(database: &str) -> String {
+    format!("{database}-client-config")
+}
+
+/// Secret holding the application credentials, usable with envFrom.
+pub fn client_credentials_name(database: &str) -> String {
+    format!("{database}-client-credentials")
+}
+
+/// Render decodes.properties. Credentials are read from the DATABASE_USERNAME and
+/// DATABASE_PASSWORD environment variables provided by the credentials Secret.
+pub fn decodes_properties(database: &OpenDcsDatabase, jdbc_uri: &str) -> String {
+    let database_type = &database.spec.database_type;
+    let mut properties = vec![
+        format!(
+            "# Generated by the OpenDCS operator for {}",
+            database.name_any()
+        ),
+        format!("EditDatabaseType={}", database_type.decodes_database_type()),
+        format!("EditDatabaseLocation={jdbc_uri}"),
+        format!("jdbcDriverClass={}", database_type.jdbc_driver()),
+        "sqlKeyGenerator=decodes.sql.SequenceKeyGenerator".to_string(),
+        "DbAuthFile=env-auth-source:username=DATABASE_USERNAME,password=DATABASE_PASSWORD"
+            .to_string(),
+    ];
+    if let Some(office) = database.spec.placeholders.get("DEFAULT_OFFICE") {
+        properties.push(format!("CwmsOfficeId={office}"));
+    }
+    properties.join("\n") + "\n"
+}
+
+fn secret_value(secret: &Secret, key: &str) -> Result<String> {
+    let value = secret
+        .data
+        .as_ref()
+        .and_then(|d| d.get(key))
+        .map(|v| String::from_utf8(v.0.clone()))
+        .or_else(|| {
+            secret
+                .string_data
+                .as_ref()
+                .and_then(|d| d.get(key))
+                .map(|v| Ok(v.clone()))
+        })
+        .ok_or_else(|| anyhow!("secret {} has no key {}", secret.name_any(), key))?;
+    Ok(value?)
+}
+
+/// Publish the client ConfigMap and Secret. They are rebuilt on every pass so a rotation of
+/// `<name>-app-user` reaches them on the next reconcile.
+pub async fn reconcile_client_config(client: &Client, database: &OpenDcsDatabase) -> Result<()> {
+    let name = database.name_any();
+    let namespace = database.namespace().unwrap_or("default".to_string());
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), &namespace);
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), &namespace);
+    let (Some(admin), Some(app_user)) = (
+        secrets.get_opt(&database.spec.database_secret).await?,
+        secrets.get_opt(&format!("{name}-app-user")).await?,
+    ) else {
+        // Nothing to publish until both secrets exist.
+        return Ok(());
+    };
+    let (config_map, credentials) = render_client_config(database, &admin, &app_user)?;
+    let pp = PatchParams::apply("database-controller");
+    config_maps
+        .patch(&client_config_name(&name), &pp, &Patch::Apply(config_map))
+        .await?;
+    secrets
+        .patch(
+            &client_credentials_name(&name),
+            &pp,
+            &Patch::Apply(credentials),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Client ConfigMap and Secret built from the database admin secret and `<name>-app-user`.
+pub fn render_client_config(
+    database: &OpenDcsDatabase,
+    admin: &Secret,
+    app_user: &Secret,
+) -> Result<(ConfigMap, Secret)> {
+    let name = database.name_any();
+    let namespace = database.namespace().unwrap_or("default".to_string());
+    let jdbc_uri = secret_value(admin, "jdbc-uri")?;
+    let decodes = decodes_properties(database, &jdbc_uri);
+    let username = secret_value(app_user, "username")?;
+    let password = secret_value(app_user, "password")?;
+    let user_properties = format!("username={username}\npassword={password}\n");
+
+    let mut hasher = Sha256::new();
+    hasher.update(decodes.as_bytes());
+    hasher.update(user_properties.as_bytes());
+    let hash = base16ct::lower::encode_string(&hasher.finalize());
+    let metadata = |object_name: String| ObjectMeta {
+        name: Some(object_name),
+        namespace: Some(namespace.clone()),
+        owner_references: Some(vec![database.controller_owner_ref(&()).unwrap()]),
+        annotations: Some(BTreeMap::from([
+            (
+                format!("{}/for-database", TSDB_GROUP.as_str()),
+                name.clone(),
+            ),
+            (format!("{}/config-hash", TSDB_GROUP.as_str()), hash.clone()),
+        ])),
+        ..Default::default()
+    };
+
+    let config_map = ConfigMap {
+        metadata: metadata(client_config_name(&name)),
+        // The connection settings are repeated as keys for applications configured through
+        // the environment.
+        data: Some(BTreeMap::from([
+            ("decodes.properties".to_string(), decodes),
+            ("DATABASE_URL".to_string(), jdbc_uri),
+            (
+                "DATABASE_DRIVER".to_string(),
+                database.spec.database_type.jdbc_driver().to_string(),
+            ),
+            (
+                "DATABASE_TYPE".to_string(),
+                database
+                    .spec
+                    .database_type
+                    .decodes_database_type()
+                    .to_string(),
+            ),
+        ])),
+        ..Default::default()
+    };
+    let credentials = Secret {
+        metadata: metadata(client_credentials_name(&name)),
+        data: Some(BTreeMap::from([
+            (
+                "user.properties".to_string(),
+                ByteString(user_properties.into_bytes()),
+            ),
+            (
+                "DATABASE_USERNAME".to_string(),
+                ByteString(username.into_bytes()),
+            ),
+            (
+                "DATABASE_PASSWORD".to_string(),
+                ByteString(password.into_bytes()),
+            ),
+        ])),
+        ..Default::default()
+    };
+    Ok((config_map, credentials))
+}
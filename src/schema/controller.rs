@@ -5,7 +5,9 @@ use crate::{
         constants::TSDB_GROUP,
         v1::tsdb::database::{MigrationState, OpenDcsDatabase, OpenDcsDatabaseStatus},
     },
-    schema::job::MigrationJob,
+    credentials::generate_password,
+    schema::{client_config::reconcile_client_config, job::MigrationJob, users::APP_ROLE_USERNAME},
+    settings::scoped_api,
     telemetry::{
         state::{Context, State},
         telemetry,
@@ -16,25 +18,42 @@ use chrono::Utc;
 use futures::StreamExt;
 use k8s_openapi::{
     ByteString,
-    api::{batch::v1::Job, core::v1::Secret},
+    api::{
+        batch::v1::Job,
+        core::v1::{ConfigMap, Secret},
+    },
 };
 use kube::{
     Api, Client, Error, Resource, ResourceExt,
     api::{ObjectMeta, Patch, PatchParams},
-    runtime::{Controller, controller::Action, watcher},
+    runtime::{
+        Controller,
+        controller::Action,
+        events::{Event, EventType},
+        watcher,
+    },
 };
-use passwords::PasswordGenerator;
 use serde_json::json;
-use tracing::{Span, field, info, instrument, warn};
+use tracing::{Span, error, field, info, instrument, warn};
 
 pub async fn run(state: State<OpenDcsDatabase>, client: Client) {
-    let databases: Api<OpenDcsDatabase> = Api::all(client.clone());
-    let jobs: Api<Job> = Api::all(client.clone());
-    let secrets: Api<Secret> = Api::all(client.clone());
+    let settings = state.settings.clone();
+    settings
+        .for_each_namespace(|namespace| run_in(state.clone(), client.clone(), namespace))
+        .await;
+}
+
+async fn run_in(state: State<OpenDcsDatabase>, client: Client, namespace: Option<String>) {
+    let databases: Api<OpenDcsDatabase> = scoped_api(client.clone(), namespace.as_deref());
+    let jobs: Api<Job> = scoped_api(client.clone(), namespace.as_deref());
+    let secrets: Api<Secret> = scoped_api(client.clone(), namespace.as_deref());
+    let config_maps: Api<ConfigMap> = scoped_api(client.clone(), namespace.as_deref());
     println!("Starting controller");
     Controller::new(databases.clone(), watcher::Config::default())
+        .with_config(state.settings.controller_config())
         .owns(jobs, watcher::Config::default())
         .owns(secrets.clone(), watcher::Config::default())
+        .owns(config_maps, watcher::Config::default())
         //     .owns(services.clone(), watcher::Config::default())
         //     .owns(cm, watcher::Config::default())
         //     .watches(secrets.clone(), user_watch_config, user_mapper)
@@ -74,24 +93,13 @@ async fn reconcile(
     let patch_name = "database-controller";
 
     if object.status.is_none() {
-        let password = PasswordGenerator {
-            length: 64,
-            numbers: true,
-            lowercase_letters: true,
-            uppercase_letters: true,
-            symbols: false,
-            spaces: false,
-            exclude_similar_characters: false,
-            strict: true,
-        }
-        .generate_one()
-        .unwrap();
+        let password = generate_password();
         let owner_ref = object.controller_owner_ref(&()).unwrap();
         let secret = Secret {
             data: Some(BTreeMap::from([
                 (
                     "username".to_string(),
-                    ByteString("dcs_admin".to_string().into_bytes()),
+                    ByteString(APP_ROLE_USERNAME.to_string().into_bytes()),
                 ),
                 ("password".to_string(), ByteString(password.into_bytes())),
             ])),
@@ -114,35 +122,131 @@ async fn reconcile(
             .await?;
     }
 
-    let migration = MigrationJob::from(&object, client).await;
-    let (old_state, new_state) = migration
-        .reconcile()
-        .await
-        .expect("No state update provided.");
+    let mut migration = match MigrationJob::from(&object, client).await {
+        Ok(migration) => migration,
+        Err(e) => {
+            error!(
+                "Unable to look up migration jobs for {}/{}: {:?}",
+                ns, name, e
+            );
+            ctx.metrics.reconcile.set_failure(&object, &e);
+            return Ok(Action::requeue(ctx.settings.error_backoff));
+        }
+    };
+    let (old_state, new_state) = match migration.reconcile().await {
+        Ok(states) => states,
+        Err(e) => {
+            error!("Unable to reconcile migration for {}/{}: {:?}", ns, name, e);
+            ctx.metrics.reconcile.set_failure(&object, &e);
+            return Ok(Action::requeue(ctx.settings.error_backoff));
+        }
+    };
 
-    if old_state == MigrationState::Fresh || old_state != new_state {
-        let version = match new_state {
-            MigrationState::Ready => Some(object.spec.schema_version.clone()),
-            _ => None,
-        };
+    if let Err(e) = reconcile_client_config(client, &object).await {
+        warn!(
+            "Unable to publish client configuration for {}/{}: {:?}",
+            ns, name, e
+        );
+        ctx.metrics.reconcile.set_failure(&object, &e);
+    }
+
+    // Migrations that finished during this pass.
+    for record in &migration.status().history {
+        if let (Some(finished), Some(outcome)) = (record.finished, &record.outcome)
+            && object.status.as_ref().is_some_and(|s| {
+                s.history
+                    .iter()
+                    .any(|r| r.job_name == record.job_name && r.finished.is_none())
+            })
+        {
+            #[allow(clippy::cast_precision_loss)]
+            let seconds = (finished - record.started).num_milliseconds() as f64 / 1000.0;
+            ctx.metrics
+                .migration
+                .observe(&name, &format!("{outcome:?}").to_lowercase(), seconds);
+        }
+    }
+
+    let mut new_status = OpenDcsDatabaseStatus {
+        last_updated: None,
+        ..migration.status().clone()
+    };
+    new_status.state = Some(new_state.clone());
+    let current_status = object.status.clone().map(|s| OpenDcsDatabaseStatus {
+        last_updated: None,
+        ..s
+    });
+    if old_state == MigrationState::Fresh || current_status.as_ref() != Some(&new_status) {
         info!(
             "Updating schema version to {:?} state = {:?}",
-            version, new_state
+            new_status.applied_schema_version, new_state
         );
+        new_status.last_updated = Some(Utc::now());
         let new_status = Patch::Apply(json!({
             "apiVersion": "tsdb.opendcs.org/v1",
             "kind": "OpenDcsDatabase",
-            "status": OpenDcsDatabaseStatus {
-                last_updated: Some(Utc::now()),
-                applied_schema_version: version,
-                state: Some(new_state),
-                }
+            "status": new_status
         }));
 
         let pp = PatchParams::apply(patch_name);
         databases.patch_status(&name, &pp, &new_status).await?;
     }
-    Ok(Action::requeue(Duration::from_secs(3600 / 2)))
+    if matches!(
+        new_state,
+        MigrationState::Failed | MigrationState::RestoreFailed
+    ) && old_state != new_state
+        && let Some(failure) = &migration.status().failure
+    {
+        ctx.recorder
+            .publish(
+                &Event {
+                    type_: EventType::Warning,
+                    reason: failure.reason.clone(),
+                    note: Some(
+                        format!("{}: {}", failure.job_name, failure.message)
+                            .chars()
+                            .take(1024)
+                            .collect(),
+                    ),
+                    action: "Migrate".into(),
+                    secondary: None,
+                },
+                &object.object_ref(&()),
+            )
+            .await?;
+    }
+    if let Some(drift) = &migration.status().schema_drift
+        && object
+            .status
+            .as_ref()
+            .is_none_or(|s| s.schema_drift.is_none())
+    {
+        ctx.recorder
+            .publish(
+                &Event {
+                    type_: EventType::Warning,
+                    reason: "SchemaDrift".into(),
+                    note: Some(format!(
+                        "flyway_schema_history changed outside of the operator (checksum {:?} -> {:?})",
+                        drift.previous_checksum, drift.checksum
+                    )),
+                    action: "Check".into(),
+                    secondary: None,
+                },
+                &object.object_ref(&()),
+            )
+            .await?;
+    }
+    let requeue = match new_state {
+        MigrationState::Checking
+        | MigrationState::Planning
+        | MigrationState::PreparingToMigrate
+        | MigrationState::BackingUp
+        | MigrationState::Migrating
+        | MigrationState::Restoring => Duration::from_secs(15),
+        _ => ctx.settings.requeue,
+    };
+    Ok(Action::requeue(requeue))
 }
 
 fn error_policy(
@@ -153,5 +257,5 @@ fn error_policy(
     warn!("reconcile failed: {:?}", err);
     let e = anyhow!("Api error {:?}", err);
     ctx.metrics.reconcile.set_failure(&object, &e);
-    Action::requeue(Duration::from_secs(5 * 60))
+    Action::requeue(ctx.settings.error_backoff)
 }
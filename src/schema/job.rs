@@ -3,281 +3,1152 @@ use std::collections::BTreeMap;
 use crate::{
     api::{
         constants::TSDB_GROUP,
-        v1::tsdb::database::{MigrationState, OpenDcsDatabase, OpenDcsDatabaseStatus},
-    }
+        v1::tsdb::database::{
+            Approval, BackupRecord, MAX_HISTORY, MigrationFailure, MigrationOutcome, MigrationPlan,
+            MigrationRecord, MigrationState, OpenDcsDatabase, OpenDcsDatabaseStatus, RestoreStatus,
+        },
+    },
+    schema::{
+        backup::{backup_job_label, create_backup_job, create_restore_job},
+        check::{self, CheckProgress},
+        plan, quiesce, rotation, users,
+    },
 };
 use anyhow::Result;
-use chrono::Utc;
-use k8s_openapi::{
-    api::{
-        batch::v1::{Job, JobSpec},
-        core::v1::{
-            Container, EnvVar, EnvVarSource, Pod, PodSpec,
-            PodTemplateSpec, SecretKeySelector, SecretVolumeSource, SecurityContext, Volume,
-            VolumeMount,
-        },
+use chrono::{Duration, Utc};
+use k8s_openapi::api::{
+    batch::v1::{Job, JobCondition, JobSpec},
+    core::v1::{
+        Container, EnvVar, EnvVarSource, Pod, PodSpec, PodTemplateSpec, Secret, SecretKeySelector,
+        SecretVolumeSource, SecurityContext, Volume, VolumeMount,
     },
-    apimachinery::pkg::apis::meta::v1::OwnerReference,
 };
 use kube::{
     Api, Client, Resource, ResourceExt,
-    api::{ListParams, ObjectMeta, Patch, PatchParams},
+    api::{DeleteParams, ListParams, LogParams, ObjectMeta, Patch, PatchParams},
 };
-use tracing::info;
+use tracing::{info, warn};
+
+/// Maximum amount of pod output kept in status.
+const MAX_LOG_BYTES: usize = 4096;
+
+/// Fetch the last `lines` lines of output from the most recent pod of a Job.
+pub async fn job_logs(
+    client: Client,
+    namespace: &str,
+    job_name: &str,
+    lines: i64,
+) -> Option<String> {
+    let logs = pod_output(client, namespace, job_name, Some(lines)).await?;
+    if logs.len() <= MAX_LOG_BYTES {
+        return Some(logs);
+    }
+    let mut start = logs.len() - MAX_LOG_BYTES;
+    while !logs.is_char_boundary(start) {
+        start += 1;
+    }
+    Some(logs[start..].to_string())
+}
+
+/// Output of the most recent pod of a Job, limited to the last `tail_lines` if given.
+pub async fn pod_output(
+    client: Client,
+    namespace: &str,
+    job_name: &str,
+    tail_lines: Option<i64>,
+) -> Option<String> {
+    let pods: Api<Pod> = Api::namespaced(client, namespace);
+    let pod = pods
+        .list(&ListParams::default().labels(&format!("job-name={job_name}")))
+        .await
+        .ok()?
+        .items
+        .into_iter()
+        .max_by_key(|p| p.metadata.creation_timestamp.clone())?;
+    pods.logs(
+        &pod.name_any(),
+        &LogParams {
+            tail_lines,
+            ..Default::default()
+        },
+    )
+    .await
+    .ok()
+}
+
+/// DATABASE_URL taken from the jdbc-uri key of the database secret.
+pub fn database_url_env(database: &OpenDcsDatabase) -> EnvVar {
+    EnvVar {
+        name: "DATABASE_URL".to_string(),
+        value_from: Some(EnvVarSource {
+            secret_key_ref: Some(SecretKeySelector {
+                key: "jdbc-uri".to_string(),
+                name: database.spec.database_secret.clone(),
+                optional: Some(true),
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Volume and mount exposing the database admin secret at /secrets/db-admin.
+pub fn admin_secret_volume(database: &OpenDcsDatabase) -> (Volume, VolumeMount) {
+    (
+        Volume {
+            name: "db-admin".to_string(),
+            secret: Some(SecretVolumeSource {
+                secret_name: Some(database.spec.database_secret.clone()),
+                optional: Some(false),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        VolumeMount {
+            name: "db-admin".to_string(),
+            mount_path: "/secrets/db-admin".to_string(),
+            ..Default::default()
+        },
+    )
+}
+
+/// A script run against the database with the admin credentials mounted at /secrets/db-admin
+/// and DATABASE_URL set.
+pub struct ScriptJob {
+    pub job_name: String,
+    /// Label, and value, used to find Jobs of this kind for a database.
+    pub label: (&'static str, String),
+    pub annotations: BTreeMap<String, String>,
+    pub image: String,
+    pub script: String,
+    pub env: Vec<EnvVar>,
+    pub volumes: Vec<(Volume, VolumeMount)>,
+}
+
+pub fn script_job(database: &OpenDcsDatabase, script_job: ScriptJob) -> Job {
+    let owner_ref = database.controller_owner_ref(&()).unwrap();
+    let namespace = database.namespace().unwrap_or("default".to_string());
+    let labels = BTreeMap::from([(script_job.label.0.to_string(), script_job.label.1)]);
+    let (admin_volume, admin_mount) = admin_secret_volume(database);
+
+    let mut env = vec![database_url_env(database)];
+    env.extend(script_job.env);
+    let mut volumes = vec![admin_volume];
+    let mut volume_mounts = vec![admin_mount];
+    for (volume, mount) in script_job.volumes {
+        volumes.push(volume);
+        volume_mounts.push(mount);
+    }
+
+    Job {
+        metadata: ObjectMeta {
+            name: Some(script_job.job_name),
+            namespace: Some(namespace),
+            owner_references: Some(vec![owner_ref]),
+            labels: Some(labels.clone()),
+            annotations: Some(script_job.annotations),
+            ..Default::default()
+        },
+        spec: Some(JobSpec {
+            backoff_limit: Some(1),
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: Some(labels),
+                    ..Default::default()
+                }),
+                spec: Some(PodSpec {
+                    containers: vec![Container {
+                        name: script_job.label.0.trim_end_matches("-job").to_string(),
+                        image: Some(script_job.image),
+                        command: Some(vec![
+                            "/bin/bash".to_string(),
+                            "-c".to_string(),
+                            script_job.script,
+                        ]),
+                        security_context: Some(SecurityContext {
+                            allow_privilege_escalation: Some(false),
+                            ..Default::default()
+                        }),
+                        env: Some(env),
+                        volume_mounts: Some(volume_mounts),
+                        ..Default::default()
+                    }],
+                    volumes: Some(volumes),
+                    restart_policy: Some("Never".to_string()),
+                    ..Default::default()
+                }),
+            },
+            ..Default::default()
+        }),
+        status: None,
+    }
+}
+
+/// The condition marking a Job as permanently failed, if present.
+pub fn failed_condition(job: &Job) -> Option<JobCondition> {
+    job.status
+        .as_ref()
+        .and_then(|s| s.conditions.as_ref())
+        .and_then(|c| {
+            c.iter()
+                .find(|c| c.type_ == "Failed" && c.status == "True")
+                .cloned()
+        })
+}
+
+/// Apply `spec.migrationJob` to a Job built for the database.
+pub fn with_job_settings(database: &OpenDcsDatabase, mut job: Job) -> Job {
+    let Some(settings) = &database.spec.migration_job else {
+        return job;
+    };
+    let Some(spec) = job.spec.as_mut() else {
+        return job;
+    };
+    if settings.backoff_limit.is_some() {
+        spec.backoff_limit = settings.backoff_limit;
+    }
+    spec.active_deadline_seconds = settings.active_deadline_seconds;
+    spec.ttl_seconds_after_finished = settings.ttl_seconds_after_finished;
+    if let Some(pod) = spec.template.spec.as_mut() {
+        if !settings.image_pull_secrets.is_empty() {
+            pod.image_pull_secrets = Some(settings.image_pull_secrets.clone());
+        }
+        if settings.service_account_name.is_some() {
+            pod.service_account_name = settings.service_account_name.clone();
+        }
+        if !settings.node_selector.is_empty() {
+            pod.node_selector = Some(settings.node_selector.clone());
+        }
+        if !settings.tolerations.is_empty() {
+            pod.tolerations = Some(settings.tolerations.clone());
+        }
+        if settings.affinity.is_some() {
+            pod.affinity = settings.affinity.clone();
+        }
+        if settings.resources.is_some() {
+            for container in pod.containers.iter_mut() {
+                container.resources = settings.resources.clone();
+            }
+        }
+    }
+    job
+}
+
+/// Create a Job for the database, then remove finished Jobs with the same labels beyond
+/// `spec.migrationJob.historyLimit`.
+pub async fn run_job(client: &Client, database: &OpenDcsDatabase, job: Job) -> Result<()> {
+    let namespace = database.namespace().unwrap_or("default".to_string());
+    let jobs: Api<Job> = Api::namespaced(client.clone(), &namespace);
+    let selector = job
+        .labels()
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<_>>()
+        .join(",");
+    jobs.patch(
+        &job.name_any(),
+        &PatchParams::apply("database-controller"),
+        &Patch::Apply(with_job_settings(database, job)),
+    )
+    .await?;
+
+    let keep = database
+        .spec
+        .migration_job
+        .clone()
+        .unwrap_or_default()
+        .history_limit as usize;
+    let mut finished: Vec<Job> = jobs
+        .list(&ListParams::default().labels(&selector))
+        .await?
+        .items
+        .into_iter()
+        .filter(|j| {
+            j.status.as_ref().and_then(|s| s.succeeded).unwrap_or(0) > 0
+                || failed_condition(j).is_some()
+        })
+        .collect();
+    finished.sort_by_key(|j| std::cmp::Reverse(j.metadata.creation_timestamp.clone()));
+    for old in finished.into_iter().skip(keep) {
+        info!("Removing old job {}/{}", &namespace, old.name_any());
+        jobs.delete(&old.name_any(), &DeleteParams::background())
+            .await?;
+    }
+    Ok(())
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref(), Some(kube::Error::Api(r)) if r.code == 404)
+}
+
+/// Most recently created Job carrying the given label.
+pub async fn latest_job(client: Client, namespace: &str, label: &str) -> Result<Option<Job>> {
+    let jobs: Api<Job> = Api::namespaced(client, namespace);
+    Ok(jobs
+        .list(&ListParams::default().labels(label))
+        .await?
+        .items
+        .into_iter()
+        .max_by_key(|j| j.metadata.creation_timestamp.clone()))
+}
+
+/// Value of the migration-job label shared by every migration Job of the database.
+pub fn migration_job_label(database: &OpenDcsDatabase) -> String {
+    format!("{}-database-migration", database.name_any())
+}
+
+/// Job running the migration image for the current schemaVersion.
+pub fn create_migration_job(
+    database: &OpenDcsDatabase,
+    job_name: &str,
+    label: (&str, String),
+    extra_env: Vec<EnvVar>,
+) -> Job {
+    let namespace = database.namespace().unwrap_or("default".to_string());
+    let owner_ref = database.controller_owner_ref(&()).unwrap();
+    let mut env: Vec<EnvVar> = Vec::new();
+    database.spec.placeholders.iter().for_each(|(k, v)| {
+        info!("Adding {k}={v}");
+        env.push(EnvVar {
+            name: format!("placeholder_{}", k),
+            value: Some(v.clone()),
+            value_from: None,
+        });
+    });
+    env.push(database_url_env(database));
+    env.push(EnvVar {
+        name: "MIGRATION_USER_FILE".to_string(),
+        value: Some("/secrets/db-admin/username".to_string()),
+        ..Default::default()
+    });
+    env.push(EnvVar {
+        name: "MIGRATION_PASSWORD_FILE".to_string(),
+        value: Some("/secrets/db-admin/password".to_string()),
+        ..Default::default()
+    });
+    env.push(EnvVar {
+        name: "APP_USER_FILE".to_string(),
+        value: Some("/secrets/db-app/username".to_string()),
+        ..Default::default()
+    });
+    env.push(EnvVar {
+        name: "APP_PASSWORD_FILE".to_string(),
+        value: Some("/secrets/db-app/password".to_string()),
+        ..Default::default()
+    });
+    let database_type = &database.spec.database_type;
+    env.push(EnvVar {
+        name: "DATABASE_TYPE".to_string(),
+        value: Some(database_type.as_str().to_string()),
+        ..Default::default()
+    });
+    env.push(EnvVar {
+        name: "DATABASE_IMPLEMENTATION".to_string(),
+        value: Some(database_type.as_str().to_string()),
+        ..Default::default()
+    });
+    if !database_type.is_postgres() {
+        env.push(EnvVar {
+            name: "SCHEMA_OWNER_FILE".to_string(),
+            value: Some("/secrets/db-admin/schema-owner".to_string()),
+            ..Default::default()
+        });
+    }
+    env.extend(extra_env);
+    let (admin_volume, admin_mount) = admin_secret_volume(database);
+    let labels = BTreeMap::from([(label.0.to_string(), label.1)]);
+    Job {
+        metadata: ObjectMeta {
+            name: Some(job_name.to_string()),
+            namespace: Some(namespace.clone()),
+            owner_references: Some(vec![owner_ref.clone()]),
+            labels: Some(labels.clone()),
+            annotations: Some(BTreeMap::from([(
+                format!("{}/schema-version", TSDB_GROUP.as_str()),
+                database.spec.schema_version.clone(),
+            )])),
+            ..Default::default()
+        },
+        spec: Some(JobSpec {
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    name: Some(job_name.to_string()),
+                    namespace: Some(namespace.clone()),
+                    owner_references: Some(vec![owner_ref.clone()]),
+                    labels: Some(labels),
+                    ..Default::default()
+                }),
+                spec: Some(PodSpec {
+                    containers: vec![Container {
+                        name: "schema-migration".to_string(),
+                        image: Some(database.spec.schema_version.clone()),
+                        security_context: Some(SecurityContext {
+                            allow_privilege_escalation: Some(false),
+                            ..Default::default()
+                        }),
+                        env: Some(env),
+                        volume_mounts: Some(vec![
+                            admin_mount,
+                            VolumeMount {
+                                name: "db-app".to_string(),
+                                mount_path: "/secrets/db-app".to_string(),
+                                ..Default::default()
+                            },
+                        ]),
+                        ..Default::default()
+                    }],
+                    volumes: Some(vec![
+                        admin_volume,
+                        Volume {
+                            name: "db-app".to_string(),
+                            secret: Some(SecretVolumeSource {
+                                secret_name: Some(format!("{}-app-user", owner_ref.name.clone())),
+                                optional: Some(false),
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        },
+                    ]),
+                    restart_policy: Some("Never".to_string()),
+                    ..Default::default()
+                }),
+            },
+            ..Default::default()
+        }),
+        status: None,
+    }
+}
 
 pub struct MigrationJob {
     database: OpenDcsDatabase,
-    owner_ref: OwnerReference,
     job: Option<Job>,
+    backup_job: Option<Job>,
     name: String,
     namespace: String,
     job_name: String,
-    status: Option<OpenDcsDatabaseStatus>,
+    status: OpenDcsDatabaseStatus,
     state: Option<MigrationState>,
     client: Client,
 }
 
 impl MigrationJob {
-    pub async fn from(database: &OpenDcsDatabase, client: &Client) -> MigrationJob {
-        let job_name = format!("{}-database-migration", database.name_any());
-        let jobs: Api<Job> = Api::namespaced(
+    pub async fn from(database: &OpenDcsDatabase, client: &Client) -> Result<MigrationJob> {
+        let job_name = migration_job_label(database);
+        let namespace = database.namespace().unwrap_or("default".to_string());
+        // Jobs are timestamped per migration; only the most recent one is of interest.
+        let job = latest_job(
             client.clone(),
-            &database.namespace().unwrap_or("default".to_string()),
-        );
-
-        let search = ListParams::default().labels(&format!("migration-job={}", &job_name));
-        let job_list = jobs
-            .list(&search)
-            .await
-            .expect("unable to search jobs")
-            .items;
-
-        let job = match job_list.first() {
-            Some(j) => Some(j.clone()),
-            None => None,
-        };
+            &namespace,
+            &format!("migration-job={}", &job_name),
+        )
+        .await?;
+        let backup_job = latest_job(
+            client.clone(),
+            &namespace,
+            &format!("backup-job={}", backup_job_label(database)),
+        )
+        .await?;
 
-        MigrationJob {
+        Ok(MigrationJob {
             client: client.clone(),
             database: database.clone(),
-            owner_ref: database.controller_owner_ref(&()).unwrap(),
-            job: job,
+            job,
+            backup_job,
             name: database.name_any().clone(),
             namespace: database.namespace().unwrap_or("default".to_string()),
             job_name: job_name.clone(),
-            status: database.status.clone(),
+            status: database.status.clone().unwrap_or_default(),
             state: database.status.as_ref().and_then(|s| s.state.clone()),
+        })
+    }
+
+    /// Status as updated by the last call to reconcile, excluding the state itself.
+    pub fn status(&self) -> &OpenDcsDatabaseStatus {
+        &self.status
+    }
+
+    pub async fn reconcile(&mut self) -> Result<(MigrationState, MigrationState)> {
+        let old_state = self.state.clone().unwrap_or(MigrationState::Fresh);
+        let schema_version = &self.database.spec.schema_version;
+        let up_to_date = self.status.applied_schema_version.as_ref() == Some(schema_version);
+        // After a restore the schema is intentionally behind the spec; wait for the spec to
+        // change before migrating again.
+        let held_by_restore = self.status.restore.as_ref().is_some_and(|r| {
+            r.completed.is_some() && r.observed_generation == self.database.metadata.generation
+        });
+        let requested_restore = self
+            .database
+            .annotations()
+            .get(&format!("{}/restore", TSDB_GROUP.as_str()))
+            .cloned();
+        // A restore waits for the active migration, backup or plan Job to finish, both would
+        // write to the same database.
+        let restore_allowed = matches!(
+            self.state,
+            Some(MigrationState::Ready)
+                | Some(MigrationState::Failed)
+                | Some(MigrationState::RestoreFailed)
+        );
+        if let Some(backup) = requested_restore
+            && restore_allowed
+            && self.status.restore.as_ref().map(|r| &r.backup) != Some(&backup)
+        {
+            let new_state = self.start_restore(&backup).await?;
+            return Ok((old_state, new_state));
+        }
+        let new_state = match &self.state {
+            Some(MigrationState::Ready) if up_to_date || held_by_restore => MigrationState::Ready,
+            Some(MigrationState::Planning) => self.check_plan().await?,
+            Some(MigrationState::AwaitingApproval) | Some(MigrationState::Checking) => {
+                self.prepare().await?
+            }
+            Some(MigrationState::Restoring) => self.check_restore().await?,
+            Some(MigrationState::RestoreFailed) => self.retry_restore().await?,
+            None | Some(MigrationState::Fresh) if self.job.is_none() => self.prepare().await?,
+            Some(MigrationState::Ready) | Some(MigrationState::PreparingToMigrate) => {
+                self.prepare().await?
+            }
+            Some(MigrationState::BackingUp) => self.check_backup().await?,
+            Some(MigrationState::Failed) => self.retry_failed().await?,
+            _ => self.check_job().await?,
+        };
+        if new_state == MigrationState::Ready {
+            self.restore_workloads().await?;
+            self.check_installed_schema().await?;
+            // Both Jobs set passwords, the users Job waits for a running rotation.
+            if !rotation::reconcile_rotation(&self.client, &self.database, &mut self.status).await?
+            {
+                users::reconcile_users(&self.client, &self.database, &mut self.status).await?;
+            }
         }
+        Ok((old_state, new_state))
     }
 
-    pub async fn reconcile(&self) -> Result<(MigrationState, MigrationState)> {
-        let status = self.status.as_ref();
-        let schema_version = self.database.spec.schema_version.clone();
-        match status {
-            Some(status) if status.state == None => self.create_job().await,
-            Some(status)
-                if status.state == Some(MigrationState::Ready)
-                    && status.applied_schema_version != Some(schema_version) =>
+    /// Scale dependent workloads to zero, returning true once their pods are gone or
+    /// the quiesce timeout has passed with `force` set.
+    async fn quiesce_dependents(&mut self) -> Result<bool> {
+        let quiesce = self.database.spec.quiesce.clone().unwrap_or_default();
+        let started = *self.status.quiesce_started.get_or_insert_with(Utc::now);
+        // Workloads are looked up on every pass so that anything started or scaled up
+        // while we wait is stopped as well, without losing the originally recorded replicas.
+        for workload in
+            quiesce::dependent_workloads(self.client.clone(), &self.namespace, &self.name).await?
+        {
+            if workload.replicas == 0 {
+                continue;
+            }
+            if !self
+                .status
+                .quiesced_workloads
+                .iter()
+                .any(|w| w.kind == workload.kind && w.name == workload.name)
             {
-                self.create_job().await
+                self.status.quiesced_workloads.push(workload.clone());
+            }
+            quiesce::scale(self.client.clone(), &self.namespace, &workload, 0).await?;
+        }
+
+        let active_pods =
+            quiesce::active_pods(self.client.clone(), &self.namespace, &self.name).await?;
+        if !active_pods.is_empty() {
+            let waited = (Utc::now() - started).num_seconds();
+            if waited < quiesce.timeout_seconds {
+                info!(
+                    "Waiting on {} dependent pods of {}/{} to stop.",
+                    active_pods.len(),
+                    &self.namespace,
+                    &self.name
+                );
+                return Ok(false);
+            } else if !quiesce.force {
+                warn!(
+                    "Dependent pods of {}/{} did not stop within {}s: {:?}. Set spec.quiesce.force to continue anyway.",
+                    &self.namespace, &self.name, quiesce.timeout_seconds, active_pods
+                );
+                return Ok(false);
             }
-            None => self.create_job().await,
-            _ => self.check_job().await,
+            warn!(
+                "Forcing past dependent pods of {}/{} still running: {:?}",
+                &self.namespace, &self.name, active_pods
+            );
         }
+        Ok(true)
     }
 
-    pub async fn create_job(&self) -> Result<(MigrationState, MigrationState)> {
-        let pods: Api<Pod> = Api::namespaced(self.client.clone(), &self.namespace);
-        let active_pods = pods
-            .list(&ListParams::default().labels(&format!(
-                "{}/for-database={}",
-                TSDB_GROUP.as_str(),
-                self.name
-            )))
-            .await?;
-        if active_pods.items.len() > 0 {
-            return Ok((
-                MigrationState::PreparingToMigrate,
-                MigrationState::PreparingToMigrate,
+    /// Problems with the spec or database secret that would keep a migration from running.
+    async fn configuration_problems(&self) -> Result<Vec<String>> {
+        let spec = &self.database.spec;
+        let mut problems = Vec::new();
+        let missing = spec.missing_placeholders();
+        if !missing.is_empty() {
+            problems.push(format!(
+                "{} requires placeholders {:?}",
+                spec.database_type.as_str(),
+                missing
+            ));
+        }
+        let secrets: Api<Secret> = Api::namespaced(self.client.clone(), &self.namespace);
+        match secrets.get_opt(&spec.database_secret).await? {
+            Some(secret) => {
+                let data = secret.data.unwrap_or_default();
+                let string_data = secret.string_data.unwrap_or_default();
+                let missing: Vec<&str> = spec
+                    .database_type
+                    .secret_keys()
+                    .iter()
+                    .filter(|k| !data.contains_key(**k) && !string_data.contains_key(**k))
+                    .copied()
+                    .collect();
+                if !missing.is_empty() {
+                    problems.push(format!(
+                        "secret {} is missing keys {:?}",
+                        &spec.database_secret, missing
+                    ));
+                }
+            }
+            None => problems.push(format!("secret {} does not exist", &spec.database_secret)),
+        }
+        let reserved = users::reserved_usernames(&self.client, &self.database).await?;
+        let conflicts = users::reserved_app_users(&spec.app_users, &reserved);
+        if !conflicts.is_empty() {
+            problems.push(format!("appUsers use reserved names {conflicts:?}"));
+        }
+        if spec.backup.is_some() && !spec.database_type.is_postgres() {
+            problems.push(format!(
+                "backups are not supported for {}",
+                spec.database_type.as_str()
             ));
         }
+        Ok(problems)
+    }
+
+    /// Scale dependent workloads to zero and start the migration once their pods are gone.
+    async fn prepare(&mut self) -> Result<MigrationState> {
+        let problems = self.configuration_problems().await?;
+        if !problems.is_empty() {
+            warn!(
+                "Unable to migrate {}/{}: {}",
+                &self.namespace,
+                &self.name,
+                problems.join(", ")
+            );
+            self.status.failure = Some(MigrationFailure {
+                job_name: String::new(),
+                reason: "InvalidConfiguration".to_string(),
+                message: problems.join(", "),
+                logs: None,
+                observed_generation: self.database.metadata.generation,
+                failed_at: Utc::now(),
+            });
+            return Ok(MigrationState::Failed);
+        }
+        // Read the schema history of databases the operator has not migrated before, so
+        // existing installations are adopted rather than treated as empty.
+        if self.status.applied_schema_version.is_none()
+            && self.status.installed_schema.is_none()
+            && self.database.spec.database_type.is_postgres()
+        {
+            match check::check_schema(
+                &self.client,
+                &self.database,
+                &mut self.status,
+                check::CHECK_INTERVAL,
+            )
+            .await?
+            {
+                CheckProgress::Running => return Ok(MigrationState::Checking),
+                CheckProgress::Failed => {
+                    self.status.failure = Some(MigrationFailure {
+                        job_name: String::new(),
+                        reason: "CheckFailed".to_string(),
+                        message: "Unable to read flyway_schema_history of the database."
+                            .to_string(),
+                        logs: None,
+                        observed_generation: self.database.metadata.generation,
+                        failed_at: Utc::now(),
+                    });
+                    return Ok(MigrationState::Failed);
+                }
+                CheckProgress::Done => {
+                    if let Some(version) = self
+                        .status
+                        .installed_schema
+                        .as_ref()
+                        .and_then(|i| i.version.as_ref())
+                    {
+                        info!(
+                            "Adopting {}/{} with installed schema {}",
+                            &self.namespace, &self.name, version
+                        );
+                    }
+                }
+            }
+        }
+        if self.database.spec.approval == Approval::Manual && !self.approved() {
+            return self.plan().await;
+        }
+        if !self.quiesce_dependents().await? {
+            return Ok(MigrationState::PreparingToMigrate);
+        }
+        // A fresh install has nothing worth backing up.
+        let installed = self.status.applied_schema_version.clone().or(self
+            .status
+            .installed_schema
+            .as_ref()
+            .and_then(|i| i.version.clone()));
+        if let (Some(backup), Some(installed)) = (&self.database.spec.backup, &installed) {
+            let backup_name = format!(
+                "{}-backup-{}",
+                &self.name,
+                Utc::now().format("%Y%m%d%H%M%S")
+            );
+            info!(
+                "Creating backup {} of {}/{} before migrating.",
+                &backup_name, &self.namespace, &self.name
+            );
+            let job = create_backup_job(&self.database, backup, &backup_name, installed);
+            run_job(&self.client, &self.database, job).await?;
+            return Ok(MigrationState::BackingUp);
+        }
+        self.create_job(None).await?;
+        Ok(MigrationState::Migrating)
+    }
+
+    /// Wait on the pre-migration backup, migrating once it completes and blocking the
+    /// migration if it fails.
+    async fn check_backup(&mut self) -> Result<MigrationState> {
+        let Some(job) = self.backup_job.clone() else {
+            return self.prepare().await;
+        };
+        let job_name = job.name_any();
+        if job.status.as_ref().and_then(|s| s.succeeded).unwrap_or(0) > 0 {
+            if !self.status.backups.iter().any(|b| b.name == job_name) {
+                self.status.backups.push(BackupRecord {
+                    name: job_name.clone(),
+                    schema_version: job
+                        .annotations()
+                        .get(&format!("{}/schema-version", TSDB_GROUP.as_str()))
+                        .cloned(),
+                    created: Utc::now(),
+                });
+                let retention = self
+                    .database
+                    .spec
+                    .backup
+                    .as_ref()
+                    .map(|b| b.retention as usize)
+                    .unwrap_or(usize::MAX);
+                let excess = self.status.backups.len().saturating_sub(retention);
+                self.status.backups.drain(..excess);
+            }
+            info!("Backup {} complete, starting migration.", &job_name);
+            self.create_job(Some(job_name)).await?;
+            return Ok(MigrationState::Migrating);
+        }
+        if let Some(condition) = failed_condition(&job) {
+            warn!(
+                "Backup job {}/{} failed, migration blocked.",
+                &self.namespace, &job_name
+            );
+            let logs = job_logs(self.client.clone(), &self.namespace, &job_name, 50).await;
+            self.status.failure = Some(MigrationFailure {
+                job_name,
+                reason: "BackupFailed".to_string(),
+                message: condition
+                    .message
+                    .unwrap_or("Pre-migration backup failed.".to_string()),
+                logs,
+                observed_generation: self.database.metadata.generation,
+                failed_at: Utc::now(),
+            });
+            return Ok(MigrationState::Failed);
+        }
+        Ok(MigrationState::BackingUp)
+    }
+
+    /// Begin restoring one of the backups recorded in status.
+    async fn start_restore(&mut self, backup: &str) -> Result<MigrationState> {
+        let record = self
+            .status
+            .backups
+            .iter()
+            .find(|b| b.name == backup)
+            .cloned();
+        self.status.restore = Some(RestoreStatus {
+            backup: backup.to_string(),
+            schema_version: record.as_ref().and_then(|r| r.schema_version.clone()),
+            observed_generation: self.database.metadata.generation,
+            started: Utc::now(),
+            completed: None,
+            job_name: None,
+        });
+        self.status.failure = None;
+        if record.is_none() || self.database.spec.backup.is_none() {
+            warn!(
+                "Backup {} of {}/{} is not known to the operator.",
+                backup, &self.namespace, &self.name
+            );
+            self.status.failure = Some(MigrationFailure {
+                job_name: String::new(),
+                reason: "BackupNotFound".to_string(),
+                message: format!(
+                    "Backup {backup} was not taken by the operator or spec.backup is not set."
+                ),
+                logs: None,
+                observed_generation: self.database.metadata.generation,
+                failed_at: Utc::now(),
+            });
+            return Ok(MigrationState::RestoreFailed);
+        }
         info!(
-            "Creating schema migration job for {}/{}",
-            &self.namespace, &self.name
+            "Restoring backup {} of {}/{}",
+            backup, &self.namespace, &self.name
         );
-        let old_state = self.state.clone().unwrap_or(MigrationState::Fresh);
-        let mut env: Vec<EnvVar> = Vec::new();
-        self.database.spec.placeholders.iter().for_each(|(k, v)| {
-            info!("Adding {k}={v}");
-            env.push(EnvVar {
-                name: format!("placeholder_{}", k),
-                value: Some(v.clone()),
-                value_from: None,
+        self.restore().await
+    }
+
+    /// Quiesce dependent workloads then run the restore Job.
+    async fn restore(&mut self) -> Result<MigrationState> {
+        if !self.quiesce_dependents().await? {
+            return Ok(MigrationState::Restoring);
+        }
+        let (Some(backup), Some(restore)) = (&self.database.spec.backup, &self.status.restore)
+        else {
+            return Ok(MigrationState::RestoreFailed);
+        };
+        let job_name = format!(
+            "{}-restore-{}",
+            &self.name,
+            Utc::now().format("%Y%m%d%H%M%S")
+        );
+        let job = create_restore_job(&self.database, backup, &job_name, &restore.backup);
+        run_job(&self.client, &self.database, job).await?;
+        if let Some(restore) = self.status.restore.as_mut() {
+            restore.job_name = Some(job_name);
+        }
+        Ok(MigrationState::Restoring)
+    }
+
+    async fn check_restore(&mut self) -> Result<MigrationState> {
+        let Some(restore) = self.status.restore.clone() else {
+            return Ok(MigrationState::RestoreFailed);
+        };
+        // Looked up by name, a second restore Job must never run against the same database.
+        let jobs: Api<Job> = Api::namespaced(self.client.clone(), &self.namespace);
+        let restore_job = match &restore.job_name {
+            Some(job_name) => jobs.get_opt(job_name).await?,
+            None => None,
+        };
+        let Some(job) = restore_job else {
+            return self.restore().await;
+        };
+        let job_name = job.name_any();
+        if job.status.as_ref().and_then(|s| s.succeeded).unwrap_or(0) > 0 {
+            info!(
+                "Restored backup {} of {}/{}",
+                &restore.backup, &self.namespace, &self.name
+            );
+            self.status.applied_schema_version = restore.schema_version.clone();
+            self.status.restore = Some(RestoreStatus {
+                completed: Some(Utc::now()),
+                ..restore
             });
-        });
-        env.push(EnvVar {
-            name: "DATABASE_URL".to_string(),
-            value_from: Some(EnvVarSource {
-                secret_key_ref: Some(SecretKeySelector {
-                    key: "jdbc-uri".to_string(),
-                    name: self.database.spec.database_secret.clone(),
-                    optional: Some(true),
-                }),
+            return Ok(MigrationState::Ready);
+        }
+        if let Some(condition) = failed_condition(&job) {
+            warn!("Restore job {}/{} failed.", &self.namespace, &job_name);
+            let logs = job_logs(self.client.clone(), &self.namespace, &job_name, 50).await;
+            self.status.failure = Some(MigrationFailure {
+                job_name,
+                reason: "RestoreFailed".to_string(),
+                message: condition
+                    .message
+                    .unwrap_or(format!("Restore of {} failed.", &restore.backup)),
+                logs,
+                observed_generation: self.database.metadata.generation,
+                failed_at: Utc::now(),
+            });
+            return Ok(MigrationState::RestoreFailed);
+        }
+        Ok(MigrationState::Restoring)
+    }
+
+    /// A failed restore is retried when the `tsdb.opendcs.org/retry-migration` annotation
+    /// is set to a new value, or replaced by naming a different backup.
+    async fn retry_restore(&mut self) -> Result<MigrationState> {
+        let retry = self
+            .database
+            .annotations()
+            .get(&format!("{}/retry-migration", TSDB_GROUP.as_str()))
+            .cloned();
+        if retry.is_none() || retry == self.status.last_retry {
+            return Ok(MigrationState::RestoreFailed);
+        }
+        self.status.last_retry = retry;
+        match self.status.restore.clone() {
+            Some(restore) => self.start_restore(&restore.backup).await,
+            None => Ok(MigrationState::RestoreFailed),
+        }
+    }
+
+    /// A failed migration is left alone until the spec changes or the
+    /// `tsdb.opendcs.org/retry-migration` annotation is set to a new value.
+    async fn retry_failed(&mut self) -> Result<MigrationState> {
+        let retry = self
+            .database
+            .annotations()
+            .get(&format!("{}/retry-migration", TSDB_GROUP.as_str()))
+            .cloned();
+        let spec_changed = self
+            .status
+            .failure
+            .as_ref()
+            .is_none_or(|f| f.observed_generation != self.database.metadata.generation);
+        let retry_requested = retry.is_some() && retry != self.status.last_retry;
+        if !spec_changed && !retry_requested {
+            return Ok(MigrationState::Failed);
+        }
+        info!(
+            "Retrying migration of {}/{} (spec changed: {}, retry requested: {})",
+            &self.namespace, &self.name, spec_changed, retry_requested
+        );
+        self.status.last_retry = retry;
+        self.status.failure = None;
+        self.job = None;
+        self.prepare().await
+    }
+
+    /// Return dependent workloads to the replicas recorded before the migration.
+    async fn restore_workloads(&mut self) -> Result<()> {
+        for workload in &self.status.quiesced_workloads {
+            let scaled = quiesce::scale(
+                self.client.clone(),
+                &self.namespace,
+                workload,
+                workload.replicas,
+            )
+            .await;
+            // A workload deleted while quiesced has nothing left to restore.
+            match scaled {
+                Err(e) if is_not_found(&e) => {
+                    info!(
+                        "{} {}/{} is gone, not restoring it",
+                        workload.kind, &self.namespace, workload.name
+                    );
+                }
+                scaled => scaled?,
+            }
+        }
+        self.status.quiesced_workloads.clear();
+        self.status.quiesce_started = None;
+        Ok(())
+    }
+
+    /// Keep `status.installed_schema` current, reading it right away after the applied
+    /// version changed and otherwise every CHECK_INTERVAL.
+    async fn check_installed_schema(&mut self) -> Result<()> {
+        if !self.database.spec.database_type.is_postgres() {
+            return Ok(());
+        }
+        let max_age = match &self.status.installed_schema {
+            Some(installed) if installed.schema_version == self.status.applied_schema_version => {
+                check::CHECK_INTERVAL
+            }
+            _ => Duration::zero(),
+        };
+        if check::check_schema(&self.client, &self.database, &mut self.status, max_age).await?
+            == CheckProgress::Failed
+        {
+            warn!(
+                "Unable to check the installed schema of {}/{}",
+                &self.namespace, &self.name
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the approve annotation names the current schemaVersion.
+    fn approved(&self) -> bool {
+        self.database
+            .annotations()
+            .get(&format!("{}/approve", TSDB_GROUP.as_str()))
+            == Some(&self.database.spec.schema_version)
+    }
+
+    /// Record the migrations the new schemaVersion would apply, reusing an existing plan
+    /// made against the same installed version.
+    async fn plan(&mut self) -> Result<MigrationState> {
+        let schema_version = &self.database.spec.schema_version;
+        if self.status.plan.as_ref().is_some_and(|p| {
+            &p.schema_version == schema_version
+                && p.from_version == self.status.applied_schema_version
+        }) {
+            return Ok(MigrationState::AwaitingApproval);
+        }
+        let job_name = format!("{}-plan-{}", &self.name, Utc::now().format("%Y%m%d%H%M%S"));
+        info!(
+            "Creating plan job {}/{} for {}",
+            &self.namespace, &job_name, schema_version
+        );
+        let job = self.migration_job(
+            &job_name,
+            ("plan-job", plan::plan_job_label(&self.database)),
+            vec![EnvVar {
+                name: "MIGRATION_MODE".to_string(),
+                value: Some("info".to_string()),
                 ..Default::default()
-            }),
-            ..Default::default()
-        });
-        env.push(EnvVar {
-            name: "MIGRATION_USER_FILE".to_string(),
-            value: Some("/secrets/db-admin/username".to_string()),
-            ..Default::default()
-        });
-        env.push(EnvVar {
-            name: "MIGRATION_PASSWORD_FILE".to_string(),
-            value: Some("/secrets/db-admin/password".to_string()),
-            ..Default::default()
-        });
-        env.push(EnvVar {
-            name: "APP_USER_FILE".to_string(),
-            value: Some("/secrets/db-app/username".to_string()),
-            ..Default::default()
-        });
-        env.push(EnvVar {
-            name: "APP_PASSWORD_FILE".to_string(),
-            value: Some("/secrets/db-app/password".to_string()),
-            ..Default::default()
-        });
-        env.push(EnvVar {
-            name: "DATABASE_TYPE".to_string(),
-            value: Some("OpenDCS-Postgres".to_string()),
-            ..Default::default()
-        });
-        env.push(EnvVar {
-            name: "DATABASE_IMPLEMENTATION".to_string(),
-            value: Some("OpenDCS-Postgres".to_string()),
-            ..Default::default()
+            }],
+        );
+        run_job(&self.client, &self.database, job).await?;
+        Ok(MigrationState::Planning)
+    }
+
+    async fn check_plan(&mut self) -> Result<MigrationState> {
+        let schema_annotation = format!("{}/schema-version", TSDB_GROUP.as_str());
+        let job = latest_job(
+            self.client.clone(),
+            &self.namespace,
+            &format!("plan-job={}", plan::plan_job_label(&self.database)),
+        )
+        .await?
+        .filter(|j| {
+            j.annotations().get(&schema_annotation) == Some(&self.database.spec.schema_version)
         });
+        let Some(job) = job else {
+            // The schemaVersion changed while planning.
+            return self.prepare().await;
+        };
+        let job_name = job.name_any();
+        if job.status.as_ref().and_then(|s| s.succeeded).unwrap_or(0) > 0 {
+            let output = pod_output(self.client.clone(), &self.namespace, &job_name, None)
+                .await
+                .unwrap_or_default();
+            let pending = plan::parse_pending(&output);
+            info!(
+                "Migration of {}/{} to {} has {} pending migrations, waiting on approval.",
+                &self.namespace,
+                &self.name,
+                &self.database.spec.schema_version,
+                pending.len()
+            );
+            self.status.plan = Some(MigrationPlan {
+                schema_version: self.database.spec.schema_version.clone(),
+                from_version: self.status.applied_schema_version.clone(),
+                job_name,
+                pending,
+                created: Utc::now(),
+            });
+            // Approval may already have been given while the plan was made.
+            self.prepare().await
+        } else if let Some(condition) = failed_condition(&job) {
+            warn!("Plan job {}/{} failed.", &self.namespace, &job_name);
+            let logs = job_logs(self.client.clone(), &self.namespace, &job_name, 50).await;
+            self.status.failure = Some(MigrationFailure {
+                job_name,
+                reason: "PlanFailed".to_string(),
+                message: condition
+                    .message
+                    .unwrap_or("Listing pending migrations failed.".to_string()),
+                logs,
+                observed_generation: self.database.metadata.generation,
+                failed_at: Utc::now(),
+            });
+            Ok(MigrationState::Failed)
+        } else {
+            Ok(MigrationState::Planning)
+        }
+    }
+
+    /// Start the migration Job, recording it in `status.history` along with the backup
+    /// taken beforehand.
+    pub async fn create_job(&mut self, backup: Option<String>) -> Result<()> {
+        info!(
+            "Creating schema migration job for {}/{}",
+            &self.namespace, &self.name
+        );
         let dt = Utc::now().format("%Y%m%d%Y%H%M%S");
         let job_name = format!("{}-database-migration-{}", &self.name, &dt);
-        let job = Job {
-            metadata: ObjectMeta {
-                name: Some(job_name.clone()),
-                namespace: Some(self.namespace.clone()),
-                owner_references: Some(vec![self.owner_ref.clone()]),
-                labels: Some(BTreeMap::from([(
-                    "migration-job".into(),
-                    self.job_name.clone(),
-                )])),
-                ..Default::default()
-            },
-            spec: Some(JobSpec {
-                template: PodTemplateSpec {
-                    metadata: Some(ObjectMeta {
-                        name: Some(job_name.clone()),
-                        namespace: Some(self.namespace.clone()),
-                        owner_references: Some(vec![self.owner_ref.clone()]),
-                        labels: Some(BTreeMap::from([(
-                            "migration-job".into(),
-                            self.job_name.clone(),
-                        )])),
-                        ..Default::default()
-                    }),
-                    spec: Some(PodSpec {
-                        containers: vec![Container {
-                            name: "schema-migration".to_string(),
-                            image: Some(self.database.spec.schema_version.clone()),
-                            security_context: Some(SecurityContext {
-                                allow_privilege_escalation: Some(false),
-                                ..Default::default()
-                            }),
-                            env: Some(env),
-                            volume_mounts: Some(vec![
-                                VolumeMount {
-                                    name: "db-admin".to_string(),
-                                    mount_path: "/secrets/db-admin".to_string(),
-                                    ..Default::default()
-                                },
-                                VolumeMount {
-                                    name: "db-app".to_string(),
-                                    mount_path: "/secrets/db-app".to_string(),
-                                    ..Default::default()
-                                },
-                            ]),
-                            ..Default::default()
-                        }],
-                        volumes: Some(vec![
-                            Volume {
-                                name: "db-admin".to_string(),
-                                secret: Some(SecretVolumeSource {
-                                    secret_name: Some(self.database.spec.database_secret.clone()),
-                                    optional: Some(false),
-                                    ..Default::default()
-                                }),
-                                ..Default::default()
-                            },
-                            Volume {
-                                name: "db-app".to_string(),
-                                secret: Some(SecretVolumeSource {
-                                    secret_name: Some(format!(
-                                        "{}-app-user",
-                                        self.owner_ref.name.clone()
-                                    )),
-                                    optional: Some(false),
-                                    ..Default::default()
-                                }),
-                                ..Default::default()
-                            },
-                        ]),
-                        restart_policy: Some("Never".to_string()),
-                        ..Default::default()
-                    }),
-                },
-                ..Default::default()
-            }),
-            status: None,
-        };
-        let patch_name = "database-controller";
-        let pp = PatchParams::apply(patch_name);
-        let jobs: Api<Job> = Api::namespaced(self.client.clone(), &self.namespace);
-        jobs.patch(&job.name_any(), &pp, &Patch::Apply(job)).await?;
-        /*let events: Api<Event> =Api::namespaced(self.client.clone(), &self.namespace);
-        events.create( &PostParams {
-            dry_run: false,
-            ..Default::default()
-        }, &Event {
-            metadata: ObjectMeta {
-                name: Some("State".to_string()), // TODO: needs randomness must be unique
-                namespace: Some(self.namespace.clone()),
-                owner_references: Some(vec![self.owner_ref.clone()]),
-                ..Default::default()
-            },
-            action: Some("Migration Job created.".to_string()),
-            message: Some("Migration Job created.".to_string()),
-            ..Default::default()
-        }).await?;*/
-        Ok((old_state, MigrationState::Fresh))
+        let job = self.migration_job(&job_name, ("migration-job", self.job_name.clone()), vec![]);
+        run_job(&self.client, &self.database, job).await?;
+        self.status.history.push(MigrationRecord {
+            from_version: self.status.applied_schema_version.clone(),
+            to_version: self.database.spec.schema_version.clone(),
+            started: Utc::now(),
+            finished: None,
+            outcome: None,
+            job_name,
+            backup,
+        });
+        let excess = self.status.history.len().saturating_sub(MAX_HISTORY);
+        self.status.history.drain(..excess);
+        Ok(())
+    }
+
+    /// Mark the history entry of a migration Job as finished.
+    fn finish_history(&mut self, job_name: &str, outcome: MigrationOutcome) {
+        if let Some(record) = self
+            .status
+            .history
+            .iter_mut()
+            .rfind(|r| r.job_name == job_name && r.finished.is_none())
+        {
+            record.finished = Some(Utc::now());
+            record.outcome = Some(outcome);
+        }
     }
 
-    pub async fn check_job(&self) -> Result<(MigrationState, MigrationState)> {
+    /// Job running the migration image for the current schemaVersion.
+    fn migration_job(&self, job_name: &str, label: (&str, String), extra_env: Vec<EnvVar>) -> Job {
+        create_migration_job(&self.database, job_name, label, extra_env)
+    }
+
+    pub async fn check_job(&mut self) -> Result<MigrationState> {
         info!(
             "Checking on schema migration job for {}/{}",
             &self.namespace, &self.name
         );
-        let old_state = self.state.clone().unwrap_or(MigrationState::Fresh);
+        let schema_annotation = format!("{}/schema-version", TSDB_GROUP.as_str());
         match &self.job {
-            Some(job) => {
-                let status = job.status.as_ref().unwrap();
+            Some(job)
+                if job.annotations().get(&schema_annotation)
+                    == Some(&self.database.spec.schema_version) =>
+            {
+                let status = job.status.clone().unwrap_or_default();
                 let ready = status.ready.unwrap_or(0);
                 let success = status.succeeded.unwrap_or(0);
-                if ready > 0 {
-                    Ok((old_state, MigrationState::Migrating))
-                } else if success > 0 {
-                    Ok((old_state, MigrationState::Ready))
+                let failed = status.failed.unwrap_or(0);
+                if success > 0 {
+                    self.finish_history(&job.name_any(), MigrationOutcome::Succeeded);
+                    self.status.applied_schema_version =
+                        Some(self.database.spec.schema_version.clone());
+                    Ok(MigrationState::Ready)
+                } else if let Some(condition) = failed_condition(job) {
+                    let job_name = job.name_any();
+                    warn!(
+                        "Schema migration job {}/{} failed after {} attempts.",
+                        &self.namespace, &job_name, failed
+                    );
+                    let logs = job_logs(self.client.clone(), &self.namespace, &job_name, 50).await;
+                    self.finish_history(&job_name, MigrationOutcome::Failed);
+                    self.status.failure = Some(MigrationFailure {
+                        job_name,
+                        reason: condition.reason.unwrap_or("JobFailed".to_string()),
+                        message: condition
+                            .message
+                            .unwrap_or(format!("Migration job failed after {failed} attempts.")),
+                        logs,
+                        observed_generation: self.database.metadata.generation,
+                        failed_at: Utc::now(),
+                    });
+                    Ok(MigrationState::Failed)
+                } else if ready > 0 {
+                    Ok(MigrationState::Migrating)
                 } else {
-                    Ok((old_state, MigrationState::PreparingToMigrate))
+                    Ok(self.state.clone().unwrap_or(MigrationState::Migrating))
                 }
             }
-            None => Ok((old_state, MigrationState::Fresh)),
+            // No job, or one left over from a previous schema version.
+            _ => self.prepare().await,
         }
     }
 }
@@ -1,2 +1,9 @@
+pub mod backup;
+pub mod check;
+pub mod client_config;
 pub mod controller;
 pub mod job;
+pub mod plan;
+pub mod quiesce;
+pub mod rotation;
+pub mod users;
@@ -0,0 +1,85 @@
+use crate::api::v1::tsdb::database::{OpenDcsDatabase, PendingMigration};
+use kube::ResourceExt;
+
+/// Label used to find the plan Jobs of a database.
+pub fn plan_job_label(database: &OpenDcsDatabase) -> String {
+    format!("{}-database-plan", database.name_any())
+}
+
+/// Pending migrations listed in the table printed by `flyway info`. Columns are located
+/// through the header row so that the table layouts of different Flyway releases work.
+pub fn parse_pending(output: &str) -> Vec<PendingMigration> {
+    let mut columns: Option<(usize, usize, usize)> = None;
+    let mut pending = Vec::new();
+    for line in output.lines().map(str::trim) {
+        if !line.starts_with('|') {
+            continue;
+        }
+        let cells: Vec<&str> = line.trim_matches('|').split('|').map(str::trim).collect();
+        let position = |name: &str| cells.iter().position(|c| c.eq_ignore_ascii_case(name));
+        if let (Some(version), Some(description), Some(state)) = (
+            position("Version"),
+            position("Description"),
+            position("State"),
+        ) {
+            columns = Some((version, description, state));
+            continue;
+        }
+        let Some((version, description, state)) = columns else {
+            continue;
+        };
+        if cells.get(state) == Some(&"Pending") {
+            pending.push(PendingMigration {
+                version: cells.get(version).unwrap_or(&"").to_string(),
+                description: cells.get(description).unwrap_or(&"").to_string(),
+            });
+        }
+    }
+    pending
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pending_migrations_are_read_from_flyway_info() {
+        let output = "
+Flyway OSS Edition 10.17.0 by Redgate
+Schema version: 7.0.13
++------------+---------+------------------+------+---------------------+---------+----------+
+| Category   | Version | Description      | Type | Installed On        | State   | Undoable |
++------------+---------+------------------+------+---------------------+---------+----------+
+| Versioned  | 7.0.13  | baseline         | SQL  | 2024-01-01 00:00:00 | Success | No       |
+| Versioned  | 7.0.14  | add site columns | SQL  |                     | Pending | No       |
+| Repeatable |         | views            | SQL  |                     | Pending | No       |
++------------+---------+------------------+------+---------------------+---------+----------+
+";
+        assert_eq!(
+            parse_pending(output),
+            vec![
+                PendingMigration {
+                    version: "7.0.14".to_string(),
+                    description: "add site columns".to_string(),
+                },
+                PendingMigration {
+                    version: String::new(),
+                    description: "views".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn older_table_layout_is_supported() {
+        let output = "
++---------+-------------+---------------------+---------+
+| Version | Description | Installed on        | State   |
++---------+-------------+---------------------+---------+
+| 1       | init        |                     | Pending |
++---------+-------------+---------------------+---------+
+";
+        assert_eq!(parse_pending(output).len(), 1);
+        assert!(parse_pending("No migrations found").is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+use crate::api::{constants::TSDB_GROUP, v1::tsdb::database::QuiescedWorkload};
+use anyhow::Result;
+use k8s_openapi::api::{
+    apps::v1::{Deployment, StatefulSet},
+    core::v1::Pod,
+};
+use kube::{
+    Api, Client, ResourceExt,
+    api::{ListParams, Patch, PatchParams},
+};
+use serde_json::json;
+use tracing::info;
+
+/// Label marking a workload or pod as depending on a database. Labeled workloads are
+/// scaled down while the database migrates.
+pub fn for_database_label(database: &str) -> (String, String) {
+    (
+        format!("{}/for-database", TSDB_GROUP.as_str()),
+        database.to_string(),
+    )
+}
+
+fn for_database(database: &str) -> ListParams {
+    let (key, value) = for_database_label(database);
+    ListParams::default().labels(&format!("{key}={value}"))
+}
+
+/// Find the Deployments and StatefulSets labeled for the given database, along with
+/// their currently requested replicas.
+pub async fn dependent_workloads(
+    client: Client,
+    namespace: &str,
+    database: &str,
+) -> Result<Vec<QuiescedWorkload>> {
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    let stateful_sets: Api<StatefulSet> = Api::namespaced(client, namespace);
+    let mut workloads = Vec::new();
+    for deployment in deployments.list(&for_database(database)).await? {
+        workloads.push(QuiescedWorkload {
+            kind: "Deployment".to_string(),
+            name: deployment.name_any(),
+            replicas: deployment.spec.and_then(|s| s.replicas).unwrap_or(1),
+        });
+    }
+    for stateful_set in stateful_sets.list(&for_database(database)).await? {
+        workloads.push(QuiescedWorkload {
+            kind: "StatefulSet".to_string(),
+            name: stateful_set.name_any(),
+            replicas: stateful_set.spec.and_then(|s| s.replicas).unwrap_or(1),
+        });
+    }
+    Ok(workloads)
+}
+
+/// Set the replicas of a workload through its scale subresource.
+pub async fn scale(
+    client: Client,
+    namespace: &str,
+    workload: &QuiescedWorkload,
+    replicas: i32,
+) -> Result<()> {
+    info!(
+        "Scaling {} {}/{} to {} replicas",
+        workload.kind, namespace, workload.name, replicas
+    );
+    let patch = Patch::Merge(json!({"spec": {"replicas": replicas}}));
+    let pp = PatchParams::default();
+    match workload.kind.as_str() {
+        "StatefulSet" => {
+            let api: Api<StatefulSet> = Api::namespaced(client, namespace);
+            api.patch_scale(&workload.name, &pp, &patch).await?;
+        }
+        _ => {
+            let api: Api<Deployment> = Api::namespaced(client, namespace);
+            api.patch_scale(&workload.name, &pp, &patch).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether a pod may still use the database. Pods of finished Jobs stay around until
+/// they are cleaned up, they no longer count.
+fn is_active(pod: &Pod) -> bool {
+    !matches!(
+        pod.status.as_ref().and_then(|s| s.phase.as_deref()),
+        Some("Succeeded") | Some("Failed")
+    )
+}
+
+/// Names of pods labeled for the given database that are still pending or running.
+pub async fn active_pods(client: Client, namespace: &str, database: &str) -> Result<Vec<String>> {
+    let pods: Api<Pod> = Api::namespaced(client, namespace);
+    Ok(pods
+        .list(&for_database(database))
+        .await?
+        .items
+        .iter()
+        .filter(|p| is_active(p))
+        .map(|p| p.name_any())
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k8s_openapi::api::core::v1::PodStatus;
+
+    fn pod(phase: Option<&str>) -> Pod {
+        Pod {
+            status: phase.map(|phase| PodStatus {
+                phase: Some(phase.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn completed_pods_are_not_active() {
+        assert!(is_active(&pod(None)));
+        assert!(is_active(&pod(Some("Pending"))));
+        assert!(is_active(&pod(Some("Running"))));
+        assert!(!is_active(&pod(Some("Succeeded"))));
+        assert!(!is_active(&pod(Some("Failed"))));
+    }
+}
@@ -0,0 +1,18 @@
+#!/bin/bash
+set -euo pipefail
+
+# The database secret holds a jdbc uri, pg_restore wants the plain postgresql one.
+PG_URI="${DATABASE_URL#jdbc:}"
+PG_USER=`cat /secrets/db-admin/username`
+export PGPASSWORD=`cat /secrets/db-admin/password`
+
+BACKUP_FILE="${BACKUP_DIR}/${BACKUP_NAME}.dump"
+if [ ! -f "${BACKUP_FILE}" ]
+then
+    echo "Backup ${BACKUP_FILE} does not exist."
+    exit 1
+fi
+
+pg_restore --dbname="${PG_URI}" --username="${PG_USER}" --clean --if-exists \
+    --single-transaction "${BACKUP_FILE}"
+echo "Restore of ${BACKUP_NAME} complete."
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    api::{
+        constants::TSDB_GROUP,
+        v1::tsdb::database::{OpenDcsDatabase, OpenDcsDatabaseStatus},
+    },
+    credentials::{generate_password, rotation_due},
+    schema::{
+        job::{failed_condition, run_job},
+        users::{app_user_secret_name, users_script_job},
+    },
+};
+use anyhow::Result;
+use chrono::Utc;
+use k8s_openapi::{
+    ByteString,
+    api::{batch::v1::Job, core::v1::Secret},
+};
+use kube::{
+    Api, Client, Resource, ResourceExt,
+    api::{DeleteParams, ObjectMeta, Patch, PatchParams},
+};
+use serde_json::json;
+use tracing::{info, warn};
+
+fn rotation_job_label(database: &OpenDcsDatabase) -> String {
+    format!("{}-credential-rotation", database.name_any())
+}
+
+/// Secret holding a new password until the rotation Job has applied it.
+fn staging_secret_name(secret: &str) -> String {
+    format!("{secret}-rotation")
+}
+
+/// Secrets holding passwords generated by the operator for the database.
+fn managed_secrets(database: &OpenDcsDatabase, status: &OpenDcsDatabaseStatus) -> Vec<String> {
+    let mut secrets = vec![format!("{}-app-user", database.name_any())];
+    secrets.extend(
+        status
+            .app_users
+            .iter()
+            .map(|user| app_user_secret_name(database, &user.name)),
+    );
+    secrets
+}
+
+/// Rotate the operator managed passwords when the policy or the rotate annotation asks
+/// for it. New passwords are staged in separate Secrets and only copied over the live ones
+/// once the database has accepted them, so applications never see an unusable password.
+/// Returns true while a rotation Job is running.
+pub async fn reconcile_rotation(
+    client: &Client,
+    database: &OpenDcsDatabase,
+    status: &mut OpenDcsDatabaseStatus,
+) -> Result<bool> {
+    let name = database.name_any();
+    let namespace = database.namespace().unwrap_or("default".to_string());
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), &namespace);
+    let jobs: Api<Job> = Api::namespaced(client.clone(), &namespace);
+    let requested = database
+        .annotations()
+        .get(&format!("{}/rotate-credentials", TSDB_GROUP.as_str()))
+        .cloned();
+    let new_request = requested.is_some() && requested != status.last_rotation_request;
+
+    if let Some(job_name) = status.rotation_job.clone() {
+        let job = jobs.get_opt(&job_name).await?;
+        let failed = job.as_ref().and_then(failed_condition).is_some();
+        match job {
+            Some(job) if job.status.as_ref().and_then(|s| s.succeeded).unwrap_or(0) > 0 => {
+                for secret in managed_secrets(database, status) {
+                    let staged = secrets.get_opt(&staging_secret_name(&secret)).await?;
+                    if let Some(password) = staged
+                        .and_then(|s| s.data)
+                        .and_then(|d| d.get("password").cloned())
+                    {
+                        let patch = json!({"data": {"password": password}});
+                        secrets
+                            .patch(&secret, &PatchParams::default(), &Patch::Merge(patch))
+                            .await?;
+                    }
+                }
+                info!("Credentials of {}/{} rotated.", &namespace, &name);
+                status.credentials_rotated = Some(Utc::now());
+            }
+            Some(_) if !failed => {
+                info!("Waiting on rotation job {}/{}", &namespace, &job_name);
+                return Ok(true);
+            }
+            Some(_) if !new_request => {
+                warn!(
+                    "Rotation job {}/{} failed, some users may already be on the staged passwords. Set the rotate-credentials annotation to a new value to try again.",
+                    &namespace, &job_name
+                );
+                return Ok(false);
+            }
+            _ => info!(
+                "Discarding credential rotation {}/{}",
+                &namespace, &job_name
+            ),
+        }
+        discard_staged(&secrets, database, status).await?;
+        status.rotation_job = None;
+    }
+
+    // Databases without a recorded rotation start their schedule now.
+    if status.credentials_rotated.is_none() {
+        status.credentials_rotated = Some(Utc::now());
+    }
+    if !rotation_due(
+        database.spec.credential_rotation.as_ref(),
+        status.credentials_rotated,
+        requested.as_ref(),
+        status.last_rotation_request.as_ref(),
+    ) {
+        return Ok(false);
+    }
+    if !database.spec.database_type.is_postgres() {
+        warn!(
+            "Credential rotation for {}/{} is not supported for {}",
+            &namespace,
+            &name,
+            database.spec.database_type.as_str()
+        );
+        status.last_rotation_request = requested;
+        return Ok(false);
+    }
+    if database.spec.app_users != status.app_users {
+        info!(
+            "Credential rotation for {}/{} waits on application users to be applied.",
+            &namespace, &name
+        );
+        return Ok(false);
+    }
+    status.last_rotation_request = requested;
+
+    let owner_ref = database.controller_owner_ref(&()).unwrap();
+    let mut staged = Vec::new();
+    for secret_name in managed_secrets(database, status) {
+        let Some(mut data) = secrets.get_opt(&secret_name).await?.and_then(|s| s.data) else {
+            continue;
+        };
+        data.insert(
+            "password".to_string(),
+            ByteString(generate_password().into_bytes()),
+        );
+        let staging = Secret {
+            data: Some(data),
+            metadata: ObjectMeta {
+                name: Some(staging_secret_name(&secret_name)),
+                namespace: Some(namespace.clone()),
+                owner_references: Some(vec![owner_ref.clone()]),
+                annotations: Some(BTreeMap::from([(
+                    format!("{}/for-database", TSDB_GROUP.as_str()),
+                    name.clone(),
+                )])),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        secrets
+            .patch(
+                &staging.name_any(),
+                &PatchParams::apply("database-controller").force(),
+                &Patch::Apply(staging),
+            )
+            .await?;
+        staged.push(staging_secret_name(&secret_name));
+    }
+
+    let job_name = format!("{}-rotate-{}", &name, Utc::now().format("%Y%m%d%H%M%S"));
+    info!(
+        "Creating rotation job {}/{} for {:?}",
+        &namespace, &job_name, staged
+    );
+    let job = users_script_job(
+        database,
+        &job_name,
+        ("rotation-job", rotation_job_label(database)),
+        BTreeMap::new(),
+        &staged,
+        &[],
+    );
+    run_job(client, database, job).await?;
+    status.rotation_job = Some(job_name);
+    Ok(true)
+}
+
+async fn discard_staged(
+    secrets: &Api<Secret>,
+    database: &OpenDcsDatabase,
+    status: &OpenDcsDatabaseStatus,
+) -> Result<()> {
+    for secret in managed_secrets(database, status) {
+        let staging = staging_secret_name(&secret);
+        if secrets.get_opt(&staging).await?.is_some() {
+            secrets.delete(&staging, &DeleteParams::default()).await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,364 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    api::{
+        constants::TSDB_GROUP,
+        v1::tsdb::database::{
+            AppUser, AppUserRole, OpenDcsDatabase, OpenDcsDatabaseStatus, POSTGRES_CLIENT_IMAGE,
+        },
+    },
+    credentials::generate_password,
+    schema::job::{ScriptJob, failed_condition, latest_job, run_job, script_job},
+};
+use anyhow::Result;
+use chrono::Utc;
+use k8s_openapi::{
+    ByteString,
+    api::{
+        batch::v1::Job,
+        core::v1::{EnvVar, Secret, SecretVolumeSource, Volume, VolumeMount},
+    },
+};
+use kube::{
+    Api, Client, Resource, ResourceExt,
+    api::{DeleteParams, ObjectMeta, Patch, PatchParams, PostParams},
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+/// Username of the `<name>-app-user` role the operator creates for applications.
+pub const APP_ROLE_USERNAME: &str = "dcs_admin";
+
+/// Usernames appUsers must not take: the operator's application role and the usernames in
+/// the database secret and `<name>-app-user`. Removing such an entry later would drop the
+/// role everything else connects with.
+pub async fn reserved_usernames(
+    client: &Client,
+    database: &OpenDcsDatabase,
+) -> Result<Vec<String>> {
+    let namespace = database.namespace().unwrap_or("default".to_string());
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), &namespace);
+    let mut reserved = vec![APP_ROLE_USERNAME.to_string()];
+    for name in [
+        database.spec.database_secret.clone(),
+        format!("{}-app-user", database.name_any()),
+    ] {
+        let username = secrets.get_opt(&name).await?.and_then(|secret| {
+            secret
+                .data
+                .and_then(|d| d.get("username").map(|u| u.0.clone()))
+                .map(|u| String::from_utf8_lossy(&u).to_string())
+                .or(secret.string_data.and_then(|d| d.get("username").cloned()))
+        });
+        reserved.extend(username);
+    }
+    Ok(reserved)
+}
+
+/// appUsers entries named like one of the reserved usernames.
+pub fn reserved_app_users(users: &[AppUser], reserved: &[String]) -> Vec<String> {
+    users
+        .iter()
+        .filter(|u| reserved.contains(&u.name))
+        .map(|u| u.name.clone())
+        .collect()
+}
+
+/// Name of the Secret holding the credentials of an application user. Underscores are
+/// allowed in role names but not in resource names, they become dashes.
+pub fn app_user_secret_name(database: &OpenDcsDatabase, user: &str) -> String {
+    format!(
+        "{}-app-user-{}",
+        database.name_any(),
+        user.replace('_', "-")
+    )
+}
+
+fn users_job_label(database: &OpenDcsDatabase) -> String {
+    format!("{}-database-users", database.name_any())
+}
+
+fn users_hash(users: &[AppUser]) -> String {
+    let hash = Sha256::digest(serde_json::to_vec(users).unwrap_or_default());
+    base16ct::lower::encode_string(&hash)
+}
+
+/// Secret with a newly generated password for an application user.
+pub fn create_user_secret(database: &OpenDcsDatabase, user: &AppUser) -> Secret {
+    let password = generate_password();
+    let owner_ref = database.controller_owner_ref(&()).unwrap();
+    Secret {
+        data: Some(BTreeMap::from([
+            (
+                "username".to_string(),
+                ByteString(user.name.clone().into_bytes()),
+            ),
+            ("password".to_string(), ByteString(password.into_bytes())),
+            (
+                "roles".to_string(),
+                ByteString(user.role.database_roles().join(",").into_bytes()),
+            ),
+        ])),
+        metadata: ObjectMeta {
+            name: Some(app_user_secret_name(database, &user.name)),
+            namespace: database.namespace(),
+            owner_references: Some(vec![owner_ref]),
+            annotations: Some(BTreeMap::from([(
+                format!("{}/for-database", TSDB_GROUP.as_str()),
+                database.name_any(),
+            )])),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Job creating or updating the given users and dropping the removed ones.
+pub fn create_users_job(
+    database: &OpenDcsDatabase,
+    job_name: &str,
+    users: &[AppUser],
+    removed: &[String],
+) -> Job {
+    let secrets: Vec<String> = users
+        .iter()
+        .map(|user| app_user_secret_name(database, &user.name))
+        .collect();
+    users_script_job(
+        database,
+        job_name,
+        ("users-job", users_job_label(database)),
+        BTreeMap::from([(
+            format!("{}/app-users-hash", TSDB_GROUP.as_str()),
+            users_hash(users),
+        )]),
+        &secrets,
+        removed,
+    )
+}
+
+/// Job running users.sh for the users held in the given Secrets. Each Secret needs a
+/// username and password, roles are optional.
+pub fn users_script_job(
+    database: &OpenDcsDatabase,
+    job_name: &str,
+    label: (&'static str, String),
+    annotations: BTreeMap<String, String>,
+    secrets: &[String],
+    removed: &[String],
+) -> Job {
+    let script = String::from_utf8(Vec::from(include_bytes!("users.sh"))).unwrap_or_default();
+    let volumes = secrets
+        .iter()
+        .enumerate()
+        .map(|(i, secret)| {
+            (
+                Volume {
+                    name: format!("user-{i}"),
+                    secret: Some(SecretVolumeSource {
+                        secret_name: Some(secret.clone()),
+                        optional: Some(false),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                VolumeMount {
+                    name: format!("user-{i}"),
+                    mount_path: format!("/secrets/app-users/{secret}"),
+                    read_only: Some(true),
+                    ..Default::default()
+                },
+            )
+        })
+        .collect();
+    script_job(
+        database,
+        ScriptJob {
+            job_name: job_name.to_string(),
+            label,
+            annotations,
+            image: POSTGRES_CLIENT_IMAGE.to_string(),
+            script,
+            env: vec![
+                EnvVar {
+                    name: "REMOVED_USERS".to_string(),
+                    value: Some(removed.join(",")),
+                    ..Default::default()
+                },
+                EnvVar {
+                    name: "MANAGED_ROLES".to_string(),
+                    value: Some(AppUserRole::all_database_roles().join(",")),
+                    ..Default::default()
+                },
+            ],
+            volumes,
+        },
+    )
+}
+
+/// Bring the database users in line with `spec.appUsers`. Status is only updated once the
+/// users Job has succeeded; Secrets of removed users are deleted at the same time.
+pub async fn reconcile_users(
+    client: &Client,
+    database: &OpenDcsDatabase,
+    status: &mut OpenDcsDatabaseStatus,
+) -> Result<()> {
+    let desired = &database.spec.app_users;
+    if *desired == status.app_users {
+        return Ok(());
+    }
+    let name = database.name_any();
+    let namespace = database.namespace().unwrap_or("default".to_string());
+    if !database.spec.database_type.is_postgres() {
+        warn!(
+            "Application users for {}/{} are not supported for {}",
+            &namespace,
+            &name,
+            database.spec.database_type.as_str()
+        );
+        return Ok(());
+    }
+    let reserved = reserved_usernames(client, database).await?;
+    let conflicts = reserved_app_users(desired, &reserved);
+    if !conflicts.is_empty() {
+        warn!(
+            "Application users of {}/{} use reserved names {:?}, not applying them.",
+            &namespace, &name, conflicts
+        );
+        return Ok(());
+    }
+    let removed: Vec<String> = status
+        .app_users
+        .iter()
+        .filter(|u| !desired.iter().any(|d| d.name == u.name) && !reserved.contains(&u.name))
+        .map(|u| u.name.clone())
+        .collect();
+    let hash = users_hash(desired);
+    let hash_annotation = format!("{}/app-users-hash", TSDB_GROUP.as_str());
+    let job = latest_job(
+        client.clone(),
+        &namespace,
+        &format!("users-job={}", users_job_label(database)),
+    )
+    .await?
+    .filter(|j| j.annotations().get(&hash_annotation) == Some(&hash));
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), &namespace);
+
+    match job {
+        Some(job) if job.status.as_ref().and_then(|s| s.succeeded).unwrap_or(0) > 0 => {
+            info!("Application users of {}/{} applied.", &namespace, &name);
+            for user in &removed {
+                let secret_name = app_user_secret_name(database, user);
+                if secrets.get_opt(&secret_name).await?.is_some() {
+                    secrets
+                        .delete(&secret_name, &DeleteParams::default())
+                        .await?;
+                }
+            }
+            status.app_users = desired.clone();
+        }
+        Some(job) if failed_condition(&job).is_some() => {
+            warn!(
+                "Users job {}/{} failed. Update spec.appUsers to try again.",
+                &namespace,
+                job.name_any()
+            );
+        }
+        Some(_) => info!("Waiting on users job for {}/{}", &namespace, &name),
+        None => {
+            for user in desired {
+                // Existing secrets keep their password, only missing ones are generated.
+                let secret_name = app_user_secret_name(database, &user.name);
+                if secrets.get_opt(&secret_name).await?.is_none() {
+                    secrets
+                        .create(&PostParams::default(), &create_user_secret(database, user))
+                        .await?;
+                } else {
+                    let roles =
+                        json!({"stringData": {"roles": user.role.database_roles().join(",")}});
+                    secrets
+                        .patch(&secret_name, &PatchParams::default(), &Patch::Merge(roles))
+                        .await?;
+                }
+            }
+            let job_name = format!("{}-users-{}", &name, Utc::now().format("%Y%m%d%H%M%S"));
+            info!(
+                "Creating users job {}/{} (removing {:?})",
+                &namespace, &job_name, removed
+            );
+            let job = create_users_job(database, &job_name, desired, &removed);
+            run_job(client, database, job).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn admin_and_app_roles_are_reserved() {
+        let users = vec![
+            AppUser {
+                name: "reporting".to_string(),
+                role: AppUserRole::ReadOnly,
+            },
+            AppUser {
+                name: APP_ROLE_USERNAME.to_string(),
+                role: AppUserRole::User,
+            },
+            AppUser {
+                name: "postgres".to_string(),
+                role: AppUserRole::Admin,
+            },
+        ];
+        let reserved = vec![APP_ROLE_USERNAME.to_string(), "postgres".to_string()];
+        assert_eq!(
+            reserved_app_users(&users, &reserved),
+            vec![APP_ROLE_USERNAME, "postgres"]
+        );
+    }
+
+    #[test]
+    fn user_secret_names_are_valid_resource_names() {
+        let database: OpenDcsDatabase = serde_json::from_value(serde_json::json!({
+            "apiVersion": "tsdb.opendcs.org/v1",
+            "kind": "OpenDcsDatabase",
+            "metadata": {"name": "db", "namespace": "tsdb", "uid": "uid"},
+            "spec": {
+                "schemaVersion": "7.0.13",
+                "databaseSecret": "db-admin",
+                "placeholders": {},
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            app_user_secret_name(&database, "read_only"),
+            "db-app-user-read-only"
+        );
+        let user = AppUser {
+            name: "read_only".to_string(),
+            role: AppUserRole::ReadOnly,
+        };
+        let secret = create_user_secret(&database, &user);
+        assert_eq!(
+            secret.metadata.name.as_deref(),
+            Some("db-app-user-read-only")
+        );
+        assert_eq!(secret.data.unwrap()["username"].0, b"read_only");
+
+        // Roles outside the current set are revoked, so the script needs all of them.
+        let job = create_users_job(&database, "db-users", &[user], &[]);
+        let env = job.spec.unwrap().template.spec.unwrap().containers[0]
+            .env
+            .clone()
+            .unwrap();
+        let managed = env.iter().find(|e| e.name == "MANAGED_ROLES").unwrap();
+        assert_eq!(
+            managed.value.as_deref(),
+            Some("pg_read_all_data,otsdb_data_acq,otsdb_comp_exec,otsdb_admin,otsdb_mgr")
+        );
+    }
+}
@@ -0,0 +1,63 @@
+#!/bin/bash
+set -euo pipefail
+
+# The database secret holds a jdbc uri, psql wants the plain postgresql one.
+PG_URI="${DATABASE_URL#jdbc:}"
+export PGUSER=`cat /secrets/db-admin/username`
+export PGPASSWORD=`cat /secrets/db-admin/password`
+
+run_sql() {
+    psql --dbname="${PG_URI}" --quiet -v ON_ERROR_STOP=1 "$@"
+}
+
+for user_dir in /secrets/app-users/*
+do
+    [ -d "${user_dir}" ] || continue
+    username=`cat ${user_dir}/username`
+    echo "Applying user ${username}"
+    run_sql -v username="${username}" -v password="`cat ${user_dir}/password`" <<'SQL'
+SELECT format('CREATE ROLE %I LOGIN', :'username')
+ WHERE NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = :'username') \gexec
+SELECT format('ALTER ROLE %I WITH LOGIN PASSWORD %L', :'username', :'password') \gexec
+SQL
+    roles=""
+    if [ -f "${user_dir}/roles" ]
+    then
+        roles=`cat ${user_dir}/roles | tr ',' ' '`
+        # Roles from an earlier appUsers role are taken away, users without a roles file
+        # keep whatever they were granted elsewhere.
+        for role in `echo "${MANAGED_ROLES:-}" | tr ',' ' '`
+        do
+            case " ${roles} " in
+                *" ${role} "*) continue ;;
+            esac
+            run_sql -v username="${username}" -v role="${role}" <<'SQL'
+SELECT format('REVOKE %I FROM %I', :'role', :'username')
+  FROM pg_auth_members m
+  JOIN pg_roles r ON r.oid = m.roleid
+  JOIN pg_roles u ON u.oid = m.member
+ WHERE r.rolname = :'role' AND u.rolname = :'username' \gexec
+SQL
+        done
+    fi
+    for role in ${roles}
+    do
+        run_sql -v username="${username}" -v role="${role}" <<'SQL'
+SELECT format('GRANT %I TO %I', :'role', :'username') \gexec
+SQL
+    done
+done
+
+for username in `echo "${REMOVED_USERS:-}" | tr ',' ' '`
+do
+    echo "Removing user ${username}"
+    run_sql -v username="${username}" <<'SQL'
+SELECT format('REASSIGN OWNED BY %I TO CURRENT_USER', :'username')
+ WHERE EXISTS (SELECT 1 FROM pg_roles WHERE rolname = :'username') \gexec
+SELECT format('DROP OWNED BY %I', :'username')
+ WHERE EXISTS (SELECT 1 FROM pg_roles WHERE rolname = :'username') \gexec
+SELECT format('DROP ROLE %I', :'username')
+ WHERE EXISTS (SELECT 1 FROM pg_roles WHERE rolname = :'username') \gexec
+SQL
+done
+echo "Users applied."
@@ -0,0 +1,131 @@
+//! Command line and environment configuration shared by the controller binaries.
+
+use std::{future::Future, time::Duration};
+
+use clap::{Args, ValueEnum};
+use futures::future::join_all;
+use kube::{Api, Client, Resource, runtime::controller};
+use serde::de::DeserializeOwned;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Compact,
+    Json,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ControllerArgs {
+    /// Address the health, metrics and diagnostics server listens on.
+    #[arg(long, env = "OPENDCS_BIND_ADDRESS", default_value = "0.0.0.0:8080")]
+    pub bind_address: String,
+    /// Worker threads of the health, metrics and diagnostics server.
+    #[arg(long, env = "OPENDCS_HTTP_WORKERS", default_value_t = 5)]
+    pub http_workers: usize,
+    /// Namespaces to watch, comma separated. Every namespace is watched when not set.
+    #[arg(
+        long = "namespace",
+        short = 'n',
+        env = "OPENDCS_WATCH_NAMESPACES",
+        value_delimiter = ','
+    )]
+    pub namespaces: Vec<String>,
+    /// Seconds between reconciles of objects that are not changing.
+    #[arg(long, env = "OPENDCS_REQUEUE_SECONDS", default_value_t = 3600 / 2)]
+    pub requeue_seconds: u64,
+    /// Seconds before retrying a failed reconcile.
+    #[arg(long, env = "OPENDCS_ERROR_BACKOFF_SECONDS", default_value_t = 5 * 60)]
+    pub error_backoff_seconds: u64,
+    /// Reconciles run at once per controller, 0 for no limit.
+    #[arg(long, env = "OPENDCS_RECONCILE_CONCURRENCY", default_value_t = 0)]
+    pub concurrency: u16,
+    /// Format of the log lines.
+    #[arg(long, env = "OPENDCS_LOG_FORMAT", value_enum, default_value_t)]
+    pub log_format: LogFormat,
+}
+
+impl ControllerArgs {
+    pub fn settings(&self) -> Settings {
+        Settings {
+            namespaces: self.namespaces.clone(),
+            requeue: Duration::from_secs(self.requeue_seconds),
+            error_backoff: Duration::from_secs(self.error_backoff_seconds),
+            concurrency: self.concurrency,
+        }
+    }
+}
+
+/// Settings the controllers read while running.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub namespaces: Vec<String>,
+    pub requeue: Duration,
+    pub error_backoff: Duration,
+    pub concurrency: u16,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            namespaces: Vec::new(),
+            requeue: Duration::from_secs(3600 / 2),
+            error_backoff: Duration::from_secs(5 * 60),
+            concurrency: 0,
+        }
+    }
+}
+
+impl Settings {
+    pub fn controller_config(&self) -> controller::Config {
+        controller::Config::default().concurrency(self.concurrency)
+    }
+
+    /// Run a controller for each watched namespace, or a single one over the cluster when
+    /// no namespaces are set. `None` stands for every namespace.
+    pub async fn for_each_namespace<F, Fut>(&self, run: F)
+    where
+        F: Fn(Option<String>) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        if self.namespaces.is_empty() {
+            run(None).await;
+        } else {
+            join_all(self.namespaces.iter().cloned().map(|ns| run(Some(ns)))).await;
+        }
+    }
+}
+
+/// Api over one namespace, or all of them for `None`.
+pub fn scoped_api<K>(client: Client, namespace: Option<&str>) -> Api<K>
+where
+    K: Resource<Scope = kube::core::NamespaceResourceScope>,
+    K::DynamicType: Default,
+    K: DeserializeOwned + Clone + std::fmt::Debug,
+{
+    match namespace {
+        Some(ns) => Api::namespaced(client, ns),
+        None => Api::all(client),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        controller: ControllerArgs,
+    }
+
+    #[test]
+    fn namespaces_are_comma_separated() {
+        let cli = Cli::parse_from(["lrgs", "-n", "lrgs,decodes", "--requeue-seconds", "60"]);
+        let settings = cli.controller.settings();
+        assert_eq!(settings.namespaces, vec!["lrgs", "decodes"]);
+        assert_eq!(settings.requeue, Duration::from_secs(60));
+        assert_eq!(settings.error_backoff, Duration::from_secs(300));
+        assert_eq!(cli.controller.log_format, LogFormat::Compact);
+    }
+}
@@ -2,33 +2,103 @@ use kube::ResourceExt;
 use opentelemetry::trace::TraceId;
 use prometheus_client::{
     encoding::EncodeLabelSet,
-    metrics::{counter::Counter, exemplar::HistogramWithExemplars, family::Family},
+    metrics::{
+        counter::Counter, exemplar::HistogramWithExemplars, family::Family, histogram::Histogram,
+    },
     registry::{Registry, Unit},
 };
-use std::sync::Arc;
 use std::{marker::PhantomData, time::SystemTime};
 use tokio::time::Instant;
 
+/// Prefix of the metrics of every controller.
+pub const METRICS_PREFIX: &str = "ctrl_reconcile";
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+pub struct ControllerLabel {
+    pub controller: String,
+}
+
+fn reconcile_histogram() -> HistogramWithExemplars<TraceLabel> {
+    HistogramWithExemplars::new([0.01, 0.1, 0.25, 0.5, 1., 5., 15., 60.].into_iter())
+}
+
+/// Metric families registered once per registry and shared by the controllers in it, which
+/// are told apart by a `controller` label.
+#[derive(Clone)]
+pub struct MetricFamilies {
+    runs: Family<ControllerLabel, Counter>,
+    failures: Family<ErrorLabels, Counter>,
+    duration: Family<
+        ControllerLabel,
+        HistogramWithExemplars<TraceLabel>,
+        fn() -> HistogramWithExemplars<TraceLabel>,
+    >,
+    migration: Family<MigrationLabels, Histogram, fn() -> Histogram>,
+}
+
+impl MetricFamilies {
+    pub fn register(r: &mut Registry) -> Self {
+        let families = Self {
+            runs: Family::default(),
+            failures: Family::default(),
+            duration: Family::new_with_constructor(reconcile_histogram),
+            migration: Family::new_with_constructor(migration_histogram),
+        };
+        r.register_with_unit(
+            "duration",
+            "reconcile duration",
+            Unit::Seconds,
+            families.duration.clone(),
+        );
+        r.register(
+            "failures",
+            "reconciliation errors",
+            families.failures.clone(),
+        );
+        r.register("runs", "reconciliations", families.runs.clone());
+        r.register_with_unit(
+            "migration_duration",
+            "schema migration duration",
+            Unit::Seconds,
+            families.migration.clone(),
+        );
+        families
+    }
+}
+
 #[derive(Clone)]
 pub struct Metrics<T: Clone + ResourceExt> {
     pub reconcile: ReconcileMetrics<T>,
-    pub registry: Arc<Registry>,
+    pub migration: MigrationMetrics,
 }
 
 impl<T: Clone + ResourceExt> Metrics<T> {
-    fn named(controller_name: &str) -> Self {
-        let mut registry = Registry::with_prefix(format!("${controller_name}ctrl_reconcile"));
-        let reconcile = ReconcileMetrics::default().register(&mut registry);
+    /// Metrics of the named controller.
+    pub fn for_controller(families: &MetricFamilies, controller_name: &str) -> Self {
+        let label = ControllerLabel {
+            controller: controller_name.to_string(),
+        };
         Self {
-            registry: Arc::new(registry),
-            reconcile,
+            reconcile: ReconcileMetrics {
+                controller: controller_name.to_string(),
+                runs: families.runs.get_or_create(&label).clone(),
+                failures: families.failures.clone(),
+                duration: families.duration.get_or_create(&label).clone(),
+                phantom: PhantomData,
+            },
+            migration: MigrationMetrics {
+                controller: controller_name.to_string(),
+                duration: families.migration.clone(),
+            },
         }
     }
 }
 
 impl<T: Clone + ResourceExt> Default for Metrics<T> {
+    /// Metrics in a registry of their own, which is not served.
     fn default() -> Self {
-        Metrics::named("ctrl_reconcile")
+        let families = MetricFamilies::register(&mut Registry::with_prefix(METRICS_PREFIX));
+        Metrics::for_controller(&families, "default")
     }
 }
 
@@ -51,27 +121,16 @@ impl TryFrom<&TraceId> for TraceLabel {
 
 #[derive(Clone)]
 pub struct ReconcileMetrics<T: Clone + ResourceExt> {
+    controller: String,
     pub runs: Counter,
     pub failures: Family<ErrorLabels, Counter>,
     pub duration: HistogramWithExemplars<TraceLabel>,
     phantom: PhantomData<T>,
 }
 
-impl<T: Clone + ResourceExt> Default for ReconcileMetrics<T> {
-    fn default() -> Self {
-        Self {
-            runs: Counter::default(),
-            failures: Family::<ErrorLabels, Counter>::default(),
-            duration: HistogramWithExemplars::new(
-                [0.01, 0.1, 0.25, 0.5, 1., 5., 15., 60.].into_iter(),
-            ),
-            phantom: PhantomData,
-        }
-    }
-}
-
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
 pub struct ErrorLabels {
+    pub controller: String,
     pub instance: String,
     pub error: String,
 }
@@ -87,22 +146,10 @@ impl MetricLabel for anyhow::Error {
 }
 
 impl<T: Clone + ResourceExt> ReconcileMetrics<T> {
-    /// Register API metrics to start tracking them.
-    pub fn register(self, r: &mut Registry) -> Self {
-        r.register_with_unit(
-            "duration",
-            "reconcile duration",
-            Unit::Seconds,
-            self.duration.clone(),
-        );
-        r.register("failures", "reconciliation errors", self.failures.clone());
-        r.register("runs", "reconciliations", self.runs.clone());
-        self
-    }
-
     pub fn set_failure(&self, obj: &T, e: &anyhow::Error) {
         self.failures
             .get_or_create(&ErrorLabels {
+                controller: self.controller.clone(),
                 instance: obj.name_any(),
                 error: e.metric_label(),
             })
@@ -119,6 +166,36 @@ impl<T: Clone + ResourceExt> ReconcileMetrics<T> {
     }
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct MigrationLabels {
+    pub controller: String,
+    pub instance: String,
+    pub outcome: String,
+}
+
+/// Schema migrations run by the database controller.
+#[derive(Clone)]
+pub struct MigrationMetrics {
+    controller: String,
+    pub duration: Family<MigrationLabels, Histogram, fn() -> Histogram>,
+}
+
+fn migration_histogram() -> Histogram {
+    Histogram::new([1., 5., 15., 30., 60., 120., 300., 600., 1800., 3600.])
+}
+
+impl MigrationMetrics {
+    pub fn observe(&self, instance: &str, outcome: &str, seconds: f64) {
+        self.duration
+            .get_or_create(&MigrationLabels {
+                controller: self.controller.clone(),
+                instance: instance.to_string(),
+                outcome: outcome.to_string(),
+            })
+            .observe(seconds);
+    }
+}
+
 /// Smart function duration measurer
 ///
 /// Relies on Drop to calculate duration and register the observation in the histogram
@@ -1,3 +1,4 @@
 pub mod metrics;
 pub mod state;
+#[allow(clippy::module_inception)]
 pub mod telemetry;
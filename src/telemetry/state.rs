@@ -7,7 +7,8 @@ use serde::Serialize;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use super::metrics::Metrics;
+use super::metrics::{MetricFamilies, Metrics};
+use crate::settings::Settings;
 
 // Context for our reconciler
 #[derive(Clone)]
@@ -21,6 +22,8 @@ pub struct Context<T: Clone + ResourceExt> {
     pub diagnostics: Arc<RwLock<Diagnostics>>,
     /// Prometheus metrics
     pub metrics: Arc<Metrics<T>>,
+    /// Requeue and backoff intervals
+    pub settings: Settings,
 }
 
 /// State shared between the controller and the web server
@@ -30,18 +33,12 @@ pub struct State<T: Clone + ResourceExt> {
     pub diagnostics: Arc<RwLock<Diagnostics>>,
     /// Prometheus metrics
     pub metrics: Arc<Metrics<T>>,
+    /// Settings handed to the controller
+    pub settings: Settings,
 }
 
 /// State wrapper around the controller outputs for the web server
 impl<T: Clone + ResourceExt> State<T> {
-    /// Metrics getter
-    pub fn metrics(&self) -> String {
-        let mut buffer = String::new();
-        let registry = &*self.metrics.registry;
-        prometheus_client::encoding::text::encode(&mut buffer, registry).unwrap();
-        buffer
-    }
-
     /// State getter
     pub async fn diagnostics(&self) -> Diagnostics {
         self.diagnostics.read().await.clone()
@@ -54,15 +51,33 @@ impl<T: Clone + ResourceExt> State<T> {
             recorder: self.diagnostics.read().await.recorder(client),
             metrics: self.metrics.clone(),
             diagnostics: self.diagnostics.clone(),
+            settings: self.settings.clone(),
         })
     }
 }
 
+impl<T: Clone + ResourceExt> State<T> {
+    /// State with metrics for the named controller in families shared with other controllers.
+    pub fn for_controller(families: &MetricFamilies, controller_name: &str) -> Self {
+        Self {
+            diagnostics: Default::default(),
+            metrics: Arc::new(Metrics::for_controller(families, controller_name)),
+            settings: Settings::default(),
+        }
+    }
+
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
 impl<T: Clone + ResourceExt> Default for State<T> {
     fn default() -> Self {
         Self {
             diagnostics: Default::default(),
             metrics: Default::default(),
+            settings: Settings::default(),
         }
     }
 }
@@ -72,6 +87,9 @@ impl<T: Clone + ResourceExt> Default for State<T> {
 pub struct Diagnostics {
     #[serde(deserialize_with = "from_ts")]
     pub last_event: DateTime<Utc>,
+    /// Replica holding the controller lease, when leader election is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leader: Option<String>,
     #[serde(skip)]
     pub reporter: Reporter,
 }
@@ -79,6 +97,7 @@ impl Default for Diagnostics {
     fn default() -> Self {
         Self {
             last_event: Utc::now(),
+            leader: None,
             reporter: "doc-controller".into(),
         }
     }
@@ -1,7 +1,9 @@
 #![allow(unused_imports)] // some used only for telemetry feature
 use opentelemetry::trace::{TraceId, TracerProvider};
 use opentelemetry_sdk::{Resource, runtime, trace as sdktrace, trace::Config};
-use tracing_subscriber::{EnvFilter, Registry, prelude::*};
+use tracing_subscriber::{EnvFilter, Layer, Registry, prelude::*};
+
+use crate::settings::LogFormat;
 
 ///  Fetch an opentelemetry::trace::TraceId as hex through the full tracing stack
 pub fn get_trace_id() -> TraceId {
@@ -43,12 +45,15 @@ fn init_tracer() -> sdktrace::Tracer {
 }
 
 /// Initialize tracing
-pub async fn init() {
+pub async fn init(log_format: LogFormat) {
     // Setup tracing layers
     #[cfg(feature = "telemetry")]
     let otel = tracing_opentelemetry::OpenTelemetryLayer::new(init_tracer());
 
-    let logger = tracing_subscriber::fmt::layer().compact();
+    let logger = match log_format {
+        LogFormat::Compact => tracing_subscriber::fmt::layer().compact().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer().json().boxed(),
+    };
     let env_filter = EnvFilter::try_from_default_env()
         .or(EnvFilter::try_new("info"))
         .unwrap();
@@ -70,7 +75,7 @@ mod test {
     #[ignore = "requires a trace exporter"]
     async fn get_trace_id_returns_valid_traces() {
         use super::*;
-        super::init().await;
+        super::init(LogFormat::Compact).await;
         #[tracing::instrument(name = "test_span")] // need to be in an instrumented fn
         fn test_trace_id() -> TraceId {
             get_trace_id()
@@ -0,0 +1,259 @@
+use std::time::Duration;
+
+use clap::Parser;
+use k8s_openapi::api::core::v1::Pod;
+use kube::{
+    Api, Client,
+    api::{Patch, PatchParams},
+    runtime::wait::await_condition,
+};
+use opendcs_controllers::{
+    api::v1::tsdb::database::{MigrationState, OpenDcsDatabase, OpenDcsDatabaseStatus},
+    schema::quiesce::for_database_label,
+};
+use serde_json::json;
+
+/// Wait for an OpenDcsDatabase to be Ready before an application starts. Meant to run as
+/// an init container.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Name of the OpenDcsDatabase.
+    #[arg(long, env = "OPENDCS_DATABASE")]
+    database: String,
+    /// Namespace of the database, defaults to the namespace of the client configuration.
+    #[arg(long, env = "POD_NAMESPACE")]
+    namespace: Option<String>,
+    /// schemaVersion (migration image) the database must have applied.
+    #[arg(long, env = "OPENDCS_SCHEMA_VERSION")]
+    schema_version: Option<String>,
+    /// Lowest installed Flyway version accepted, for example 7.0.13.
+    #[arg(long, env = "OPENDCS_MIN_SCHEMA", value_parser = version_arg)]
+    min_version: Option<String>,
+    /// Seconds to wait before giving up.
+    #[arg(long, env = "OPENDCS_WAIT_TIMEOUT", default_value_t = 600)]
+    timeout: u64,
+    /// Label the named pod, usually this one, as depending on the database so migrations
+    /// wait for it to stop.
+    #[arg(long)]
+    label_pod: Option<String>,
+}
+
+/// Numeric parts of a Flyway version and whether it is a release. A `-rc1` like suffix
+/// marks a pre-release, which comes before the release of the same numbers. None when a
+/// part is not a number.
+fn parse_version(version: &str) -> Option<(Vec<u64>, bool)> {
+    let (numbers, suffix) = match version.split_once('-') {
+        Some((numbers, suffix)) => (numbers, Some(suffix)),
+        None => (version, None),
+    };
+    let parts = numbers
+        .split('.')
+        .map(|p| p.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    Some((parts, suffix.is_none()))
+}
+
+fn version_arg(version: &str) -> Result<String, String> {
+    parse_version(version)
+        .map(|_| version.to_string())
+        .ok_or(format!("{version} is not a version like 7.0.13"))
+}
+
+/// Versions that cannot be parsed never satisfy the minimum.
+fn version_at_least(version: &str, minimum: &str) -> bool {
+    match (parse_version(version), parse_version(minimum)) {
+        (Some(version), Some(minimum)) => version >= minimum,
+        _ => false,
+    }
+}
+
+/// Whether the database is Ready with a schema the application can use.
+fn compatible(status: &OpenDcsDatabaseStatus, args: &Args) -> bool {
+    if status.state != Some(MigrationState::Ready) {
+        return false;
+    }
+    if let Some(expected) = &args.schema_version
+        && status.applied_schema_version.as_ref() != Some(expected)
+    {
+        return false;
+    }
+    if let Some(minimum) = &args.min_version {
+        let installed = status
+            .installed_schema
+            .as_ref()
+            .and_then(|i| i.version.as_deref());
+        return installed.is_some_and(|v| version_at_least(v, minimum));
+    }
+    true
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    rustls::crypto::ring::default_provider()
+        .install_default()
+        .expect("Failed to install rustls crypto provider");
+    let args = Args::parse();
+    let client = Client::try_default().await?;
+    let namespace = args
+        .namespace
+        .clone()
+        .unwrap_or(client.default_namespace().to_string());
+
+    if let Some(pod) = &args.label_pod {
+        let (key, value) = for_database_label(&args.database);
+        let pods: Api<Pod> = Api::namespaced(client.clone(), &namespace);
+        pods.patch(
+            pod,
+            &PatchParams::default(),
+            &Patch::Merge(json!({"metadata": {"labels": {key: value}}})),
+        )
+        .await?;
+    }
+
+    println!(
+        "Waiting up to {}s for {}/{} to be Ready.",
+        args.timeout, &namespace, &args.database
+    );
+    let databases: Api<OpenDcsDatabase> = Api::namespaced(client, &namespace);
+    let ready = await_condition(
+        databases.clone(),
+        &args.database,
+        |db: Option<&OpenDcsDatabase>| {
+            db.and_then(|db| db.status.as_ref())
+                .is_some_and(|s| compatible(s, &args))
+        },
+    );
+    if tokio::time::timeout(Duration::from_secs(args.timeout), ready)
+        .await
+        .is_ok_and(|r| r.is_ok())
+    {
+        println!("{}/{} is Ready.", &namespace, &args.database);
+        return Ok(());
+    }
+
+    let status = databases
+        .get_opt(&args.database)
+        .await
+        .ok()
+        .flatten()
+        .map(|db| db.status.unwrap_or_default());
+    match status {
+        None => eprintln!(
+            "OpenDcsDatabase {}/{} does not exist.",
+            &namespace, &args.database
+        ),
+        Some(status) => {
+            eprintln!(
+                "OpenDcsDatabase {}/{} not usable after {}s: state {:?}, applied schema {:?}, installed version {:?}.",
+                &namespace,
+                &args.database,
+                args.timeout,
+                status.state,
+                status.applied_schema_version,
+                status.installed_schema.and_then(|i| i.version),
+            );
+            if let Some(expected) = &args.schema_version {
+                eprintln!("Expected schemaVersion {expected}.");
+            }
+            if let Some(minimum) = &args.min_version {
+                eprintln!("Expected at least schema version {minimum}.");
+            }
+            if let Some(failure) = status.failure {
+                eprintln!("Last failure: {}: {}", failure.reason, failure.message);
+            }
+        }
+    }
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use opendcs_controllers::api::v1::tsdb::database::InstalledSchema;
+
+    fn args(extra: &[&str]) -> Args {
+        let mut argv = vec!["wait-for-database", "--database", "tsdb"];
+        argv.extend(extra);
+        Args::parse_from(argv)
+    }
+
+    fn status(state: MigrationState, applied: &str, installed: &str) -> OpenDcsDatabaseStatus {
+        OpenDcsDatabaseStatus {
+            state: Some(state),
+            applied_schema_version: Some(applied.to_string()),
+            installed_schema: Some(InstalledSchema {
+                version: Some(installed.to_string()),
+                checksum: None,
+                migrations: 1,
+                schema_version: Some(applied.to_string()),
+                job_name: "tsdb-schema-check".to_string(),
+                checked: chrono::Utc::now(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn only_ready_databases_are_compatible() {
+        let args = args(&[]);
+        assert!(compatible(
+            &status(MigrationState::Ready, "7.0.13", "7.0.13"),
+            &args
+        ));
+        assert!(!compatible(
+            &status(MigrationState::Migrating, "7.0.13", "7.0.13"),
+            &args
+        ));
+        assert!(!compatible(&OpenDcsDatabaseStatus::default(), &args));
+    }
+
+    #[test]
+    fn schema_version_must_match() {
+        let args = args(&["--schema-version", "7.0.14"]);
+        assert!(!compatible(
+            &status(MigrationState::Ready, "7.0.13", "7.0.13"),
+            &args
+        ));
+        assert!(compatible(
+            &status(MigrationState::Ready, "7.0.14", "7.0.14"),
+            &args
+        ));
+    }
+
+    #[test]
+    fn installed_version_must_reach_the_minimum() {
+        let args = args(&["--min-version", "7.0.13"]);
+        assert!(compatible(
+            &status(MigrationState::Ready, "main", "7.0.13"),
+            &args
+        ));
+        assert!(compatible(
+            &status(MigrationState::Ready, "main", "7.1"),
+            &args
+        ));
+        assert!(!compatible(
+            &status(MigrationState::Ready, "main", "7.0.12"),
+            &args
+        ));
+        assert!(!compatible(
+            &status(MigrationState::Ready, "main", "7.0.13-rc1"),
+            &args
+        ));
+        assert!(!compatible(
+            &status(MigrationState::Ready, "main", "7.0.x"),
+            &args
+        ));
+        assert!(version_at_least("7.0.13-rc1", "7.0.12"));
+        assert!(!version_at_least("7.0.13-rc1", "7.0.13"));
+        assert!(
+            Args::try_parse_from([
+                "wait-for-database",
+                "--database",
+                "tsdb",
+                "--min-version",
+                "latest"
+            ])
+            .is_err()
+        );
+    }
+}
@@ -1,8 +1,13 @@
 #[cfg(test)]
 pub mod test {
+    use k8s_openapi::api::{
+        apps::v1::{Deployment, DeploymentSpec},
+        core::v1::{Container, PodSpec, PodTemplateSpec},
+    };
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
     use kube::{
         Api, Client,
-        api::{DeleteParams, ListParams, ObjectMeta, Patch, PatchParams},
+        api::{DeleteParams, ListParams, ObjectMeta, Patch, PatchParams, PostParams},
         runtime::wait::{Condition, await_condition},
     };
     use opendcs_controllers::api::v1::tsdb::database::{
@@ -89,6 +94,54 @@ pub mod test {
         }
     }
 
+    /// Start a Deployment labeled as depending on the given OpenDcsDatabase.
+    pub async fn create_dependent_app(client: Client, database: &str, name: &str) -> Deployment {
+        let deployment_api: Api<Deployment> = Api::default_namespaced(client.clone());
+        let labels = BTreeMap::from([
+            ("app".to_string(), name.to_string()),
+            (
+                "tsdb.opendcs.org/for-database".to_string(),
+                database.to_string(),
+            ),
+        ]);
+        let deployment = Deployment {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                labels: Some(labels.clone()),
+                ..Default::default()
+            },
+            spec: Some(DeploymentSpec {
+                replicas: Some(1),
+                selector: LabelSelector {
+                    match_labels: Some(labels.clone()),
+                    ..Default::default()
+                },
+                template: PodTemplateSpec {
+                    metadata: Some(ObjectMeta {
+                        labels: Some(labels.clone()),
+                        ..Default::default()
+                    }),
+                    spec: Some(PodSpec {
+                        termination_grace_period_seconds: Some(1),
+                        containers: vec![Container {
+                            name: "app".into(),
+                            image: Some("busybox:1.36".into()),
+                            command: Some(vec!["sleep".into(), "3600".into()]),
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    }),
+                },
+                ..Default::default()
+            }),
+            status: None,
+        };
+        deployment_api
+            .create(&PostParams::default(), &deployment)
+            .await
+            .expect("Unable to create dependent application.")
+    }
+
     async fn create(client: Client, name: &str, migration_image: &str, db: &PostgresInstance) {
         let test_db_name = name;
         let pp = PatchParams::apply(name);
@@ -106,6 +159,7 @@ pub mod test {
                     ("NUM_TS_TABLES".into(), "1".into()),
                     ("NUM_TEXT_TABLES".into(), "1".into()),
                 ]),
+                ..Default::default()
             },
             status: None,
         };
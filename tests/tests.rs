@@ -3,12 +3,14 @@ mod common;
 #[cfg(test)]
 mod tests {
 
-    use opendcs_controllers::api::v1::tsdb::database::MigrationState;
+    use k8s_openapi::api::apps::v1::Deployment;
+    use kube::{Api, api::DeleteParams};
+    use opendcs_controllers::api::v1::tsdb::database::{MigrationOutcome, MigrationState};
     use rstest::rstest;
     use tracing::info;
 
     use crate::common::{
-        opendcs_database::test::OpenDcsTestDatabase,
+        opendcs_database::test::{OpenDcsTestDatabase, create_dependent_app},
         tests::{K8s, k8s_inst},
     };
 
@@ -29,6 +31,7 @@ mod tests {
         assert!(status.applied_schema_version == Some(base_image.into()));
 
         // start a depending application
+        create_dependent_app(client.clone(), "testdb-upgrade", "upgrade-dependent").await;
         // Change the schema image to trigger migration and wait.
         let odcs_db =
             OpenDcsTestDatabase::upgrade(client.clone(), "testdb-upgrade", &db, upgrade_image)
@@ -37,6 +40,27 @@ mod tests {
         let status = odcs_db.opendcs_database.status.clone().expect("No status?");
         assert!(status.state == Some(MigrationState::Ready));
         assert!(status.applied_schema_version == Some(upgrade_image.into()));
+        assert!(status.quiesced_workloads.is_empty());
+
+        // both migrations are recorded, the upgrade starting from the base image.
+        assert!(status.history.len() == 2);
+        let upgrade = status.history.last().unwrap();
+        assert!(upgrade.from_version == Some(base_image.into()));
+        assert!(upgrade.to_version == upgrade_image);
+        assert!(upgrade.outcome == Some(MigrationOutcome::Succeeded));
+        assert!(upgrade.finished.is_some());
+
+        // the dependent application is returned to its original replicas.
+        let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
+        let dependent = deployments
+            .get("upgrade-dependent")
+            .await
+            .expect("dependent application removed?");
+        assert!(dependent.spec.and_then(|s| s.replicas) == Some(1));
+        deployments
+            .delete("upgrade-dependent", &DeleteParams::default())
+            .await
+            .expect("Unable to remove dependent application.");
 
         assert!(odcs_db.delete().await);
 